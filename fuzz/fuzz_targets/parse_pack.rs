@@ -0,0 +1,17 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate tw_pack_lib;
+
+// Parsing, iterating and reading every PackedFile of a malformed PackFile must return errors, never panic.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(pack) = tw_pack_lib::parse_pack_from_bytes(data.to_vec()) {
+        let _ = pack.get_pack_file_index();
+        let _ = pack.get_signature();
+        let _ = pack.get_tree();
+        for packed_file in pack.entries().flatten() {
+            let _ = packed_file.get_data();
+            let _ = pack.contains_ignore_case(&packed_file.path);
+        }
+    }
+});
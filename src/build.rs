@@ -1,14 +1,51 @@
 use std::borrow::Borrow;
+use std::cmp;
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::fs::File;
+use std::fs::OpenOptions;
 use std::fs;
+use std::io::BufWriter;
+use std::io::ErrorKind;
 use std::io::Read;
 use std::io::Write;
+use std::ops::Range;
 use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use byteorder::LittleEndian;
 use byteorder::WriteBytesExt;
 
-use error::Result;
+use error::{Error, Result};
+use parse::LazyLoadingPackedFile;
+
+/// Size of the chunks we copy the data of a PackedFile in, when we copy it straight from his PackFile.
+const COPY_CHUNK_SIZE: u64 = 0x10_0000;
+
+/// Number of temporary files created by this process, so every save gets his own one.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// This enum represents the data of a PackedFile like it has to be written in the PackFile.
+enum StoredData {
+    /// Data in memory, not encrypted yet.
+    Memory(Arc<Vec<u8>>),
+    /// Data already stored in the PackFile of a lazy-loaded PackedFile like we need it. It's copied as it is from the range, padding included.
+    Raw(LazyLoadingPackedFile, Range<u64>)
+}
+
+impl StoredData {
+
+    /// This function returns the length of the data, without padding. It's the length written in the PackedFile Index.
+    fn len(&self) -> u64 {
+        match *self {
+            StoredData::Memory(ref data) => data.len() as u64,
+            StoredData::Raw(ref lazy, _) => lazy.range.end - lazy.range.start
+        }
+    }
+}
 
 fn traverse_directory(directory: &Path, prefix: &str) -> Result<Vec<::PackedFile>> {
     let mut files = vec!();
@@ -16,10 +53,14 @@ fn traverse_directory(directory: &Path, prefix: &str) -> Result<Vec<::PackedFile
         let entry = entry?;
         let path = entry.path();
         let metadata = fs::metadata(&path)?;
+        let file_name = entry.file_name().into_string().map_err(|file_name| Error::InvalidPathError {
+            path: file_name.to_string_lossy().into_owned(),
+            reason: "the file name is not valid UTF-8"
+        })?;
         let relative_path = if !prefix.is_empty() {
-            prefix.to_owned() + &"\\".to_owned() + &entry.file_name().into_string().unwrap()
+            prefix.to_owned() + "\\" + &file_name
         } else {
-            entry.file_name().into_string().unwrap()
+            file_name
         };
         if metadata.is_dir() {
             let child_files = traverse_directory(&path, &relative_path)?;
@@ -34,44 +75,84 @@ fn traverse_directory(directory: &Path, prefix: &str) -> Result<Vec<::PackedFile
     Ok(files)
 }
 
-fn write_header<P: Borrow<::PackedFile>>(
-    output_file: &mut File, 
-    version: ::PFHVersion, 
-    bitmask: ::PFHFlags, 
-    file_type: ::PFHFileType, 
-    pfh_timestamp: u32, 
-    pack_files_index_size: u32, 
-    packed_files_index_size: u32, 
-    pack_files: &[String],
-    packed_files: &Vec<P>
-) -> Result<()> {
-
+fn write_header<W: Write>(output_file: &mut W, builder: &::PackBuilder, pack_files_index_size: u32, packed_files_index_size: u32, signature_offset: u32) -> Result<()> {
+    let version = builder.version;
+    let bitmask = builder.bitmask;
+    let pfh_timestamp = builder.timestamp;
     output_file.write_u32::<LittleEndian>(version.get_preamble())?;
-    output_file.write_u32::<LittleEndian>(bitmask.bits | file_type.get_value())?;
-    output_file.write_u32::<LittleEndian>(pack_files.len() as u32)?; // PF Index Count
+    output_file.write_u32::<LittleEndian>(bitmask.bits | builder.file_type.get_value())?;
+    output_file.write_u32::<LittleEndian>(builder.pack_files.len() as u32)?; // PF Index Count
     output_file.write_u32::<LittleEndian>(pack_files_index_size)?; // PF Index Size
-    output_file.write_u32::<LittleEndian>(packed_files.len() as u32)?;
+    output_file.write_u32::<LittleEndian>(builder.packed_files.len() as u32)?;
     output_file.write_u32::<LittleEndian>(packed_files_index_size)?;
     match version {
+        ::PFHVersion::PFH6 => {
+            let default_subheader = ::PFHSubheader::default();
+            let subheader = builder.subheader.as_ref().unwrap_or(&default_subheader);
+            check_subheader(subheader)?;
+            output_file.write_u32::<LittleEndian>(pfh_timestamp as u32)?;
+            output_file.write_u32::<LittleEndian>(::SUBHEADER_MARK)?;
+            output_file.write_u32::<LittleEndian>(subheader.version)?;
+            output_file.write_u32::<LittleEndian>(subheader.game_version)?;
+            output_file.write_u32::<LittleEndian>(subheader.build_number)?;
+            output_file.write_all(subheader.authoring_tool.as_ref())?;
+            output_file.write_all(&vec![0; 8 - subheader.authoring_tool.len()])?;
+            output_file.write_all(&subheader.extra_data)?;
+        },
         ::PFHVersion::PFH4  => {
-            output_file.write_u32::<LittleEndian>(pfh_timestamp)?;
+            output_file.write_u32::<LittleEndian>(pfh_timestamp as u32)?;
         },
         ::PFHVersion::PFH5 => {
             if bitmask.contains(::PFHFlags::HAS_BIG_HEADER) {
-                output_file.write_u32::<LittleEndian>(0)?;
-                output_file.write_u32::<LittleEndian>(0)?;
-                output_file.write_u32::<LittleEndian>(0)?;
-                output_file.write_u32::<LittleEndian>(0)?;
-                output_file.write_u32::<LittleEndian>(0)?;
-                output_file.write_u32::<LittleEndian>(0)?;
+                let default_big_header = ::PFHBigHeader::default();
+                let big_header = builder.big_header.as_ref().unwrap_or(&default_big_header);
+                output_file.write_u32::<LittleEndian>(pfh_timestamp as u32)?;
+                output_file.write_u32::<LittleEndian>(big_header.unknown_1)?;
+                output_file.write_u32::<LittleEndian>(big_header.unknown_2)?;
+                output_file.write_u32::<LittleEndian>(big_header.unknown_3)?;
+                output_file.write_u32::<LittleEndian>(signature_offset)?;
+                output_file.write_u32::<LittleEndian>(big_header.unknown_4)?;
             } else {
-                output_file.write_u32::<LittleEndian>(pfh_timestamp)?;
+                output_file.write_u32::<LittleEndian>(pfh_timestamp as u32)?;
             }
-        }
+        },
+        ::PFHVersion::PFH3 | ::PFHVersion::PFH2 => {
+            output_file.write_u64::<LittleEndian>(pfh_timestamp)?;
+        },
+        ::PFHVersion::PFH0 => {}
+    }
+    Ok(())
+}
+
+/// This function checks that the Subheader of a PFH6 PackFile fits in his fixed-size fields.
+fn check_subheader(subheader: &::PFHSubheader) -> Result<()> {
+    if subheader.authoring_tool.len() > 8 {
+        return Err(Error::InvalidSubheaderError(format!("the authoring tool \"{}\" is longer than 8 bytes", subheader.authoring_tool)))
+    }
+    if subheader.authoring_tool.contains('\0') {
+        return Err(Error::InvalidSubheaderError(format!("the authoring tool {:?} contains a null character", subheader.authoring_tool)))
+    }
+    if subheader.extra_data.len() != 256 {
+        return Err(Error::InvalidSubheaderError(format!("the Subheader extra data is {} bytes long instead of 256", subheader.extra_data.len())))
     }
     Ok(())
 }
-fn write_pack_file_index(output_file: &mut File, pack_files: &[String]) -> Result<()> {
+
+/// This function converts a size or an offset to the 32 bits PackFiles store them in, failing if it doesn't fit.
+fn get_u32_size(size: u64) -> Result<u32> {
+    u32::try_from(size).map_err(|_| Error::UnsupportedSize { size })
+}
+
+/// This function returns the size a PackedFile takes in the PackFile, padding included.
+fn get_stored_size(data: &StoredData, version: ::PFHVersion, bitmask: ::PFHFlags) -> u64 {
+    if version.has_padding(bitmask) {
+        (data.len() + 7) & !7
+    } else {
+        data.len()
+    }
+}
+
+fn write_pack_file_index<W: Write>(output_file: &mut W, pack_files: &[String]) -> Result<()> {
     for pack_file in pack_files {
         output_file.write_all(pack_file.as_ref())?;
         output_file.write_u8(0)?;
@@ -79,67 +160,386 @@ fn write_pack_file_index(output_file: &mut File, pack_files: &[String]) -> Resul
     Ok(())
 }
 
-fn write_packed_file_index<P: Borrow<::PackedFile>>(output_file: &mut File, files: &Vec<P>, version: ::PFHVersion, bitmask: ::PFHFlags) -> Result<()> {
-    for file in files {
-        let file = file.borrow();
-        output_file.write_u32::<LittleEndian>(file.get_data()?.len() as u32)?;
-        if bitmask.contains(::PFHFlags::HAS_INDEX_WITH_TIMESTAMPS) {
-            output_file.write_u32::<LittleEndian>(file.timestamp.unwrap_or(0))?
+/// This function returns the data of a PackedFile like it has to be written in the PackFile.
+///
+/// If the PackedFile is lazy-loaded and his PackFile stores it with the same compression, encryption and padding we need, we don't
+/// load it at all: it's copied straight from his PackFile while writing. Otherwise, if the PackedFile was already compressed in his
+/// PackFile, we try to reuse the compressed data instead of compressing it again.
+fn get_stored_data(file: &::PackedFile, compress: bool, version: ::PFHVersion, bitmask: ::PFHFlags) -> Result<StoredData> {
+    if let Some(lazy) = file.get_lazy_data() {
+        if lazy.is_compressed == compress && lazy.is_encrypted == bitmask.contains(::PFHFlags::HAS_ENCRYPTED_CONTENT) && lazy.has_padding == version.has_padding(bitmask) {
+            if let Some(range) = lazy.get_stored_range() {
+                return Ok(StoredData::Raw(lazy, range))
+            }
         }
+    }
+    let data = if !compress {
+        file.get_data()?
+    } else if file.is_compressed {
+        file.get_raw_data()?
+    } else {
+        Arc::new(file.with_path(::compression::compress_data(&file.get_data()?))?)
+    };
+    Ok(StoredData::Memory(data))
+}
 
-        if version == ::PFHVersion::PFH5 && !bitmask.contains(::PFHFlags::HAS_BIG_HEADER) {
+fn write_packed_file_index<W: Write>(output_file: &mut W, files: &[&::PackedFile], files_sizes: &[u32], files_compressed: &[bool], version: ::PFHVersion, bitmask: ::PFHFlags) -> Result<()> {
+    for (index, ((file, size), compressed)) in files.iter().zip(files_sizes).zip(files_compressed).enumerate() {
+        // Encrypted indexes use the amount of items left after the current one as key.
+        let item_index = (files.len() - index - 1) as u32;
+        if bitmask.contains(::PFHFlags::HAS_ENCRYPTED_INDEX) {
+            output_file.write_u32::<LittleEndian>(::crypto::encrypt_index_item_file_length(item_index, *size))?;
+        } else {
+            output_file.write_u32::<LittleEndian>(*size)?;
+        }
+        match version.get_index_timestamp_size(bitmask) {
+            8 => output_file.write_u64::<LittleEndian>(file.timestamp.unwrap_or(0))?,
+            4 => output_file.write_u32::<LittleEndian>(file.timestamp.unwrap_or(0) as u32)?,
+            _ => {}
+        }
+
+        if version.has_index_compression_byte(bitmask) {
+            output_file.write_u8(*compressed as u8)?;
+        }
+        if bitmask.contains(::PFHFlags::HAS_ENCRYPTED_INDEX) {
+            output_file.write_all(&::crypto::encrypt_index_item_filename(file.path.as_ref(), *size as u8))?;
+        } else {
+            output_file.write_all(file.path.as_ref())?;
             output_file.write_u8(0)?;
         }
-        output_file.write_all(file.path.as_ref())?;
-        output_file.write_u8(0)?;
     }
     Ok(())
 }
 
-fn write_content<P: Borrow<::PackedFile>>(output_file: &mut File, files: &Vec<P>) -> Result<()> {
-    for file in files {
-        output_file.write_all(&file.borrow().get_data()?)?;
+fn write_content<W: Write>(output_file: &mut W, files: &[&::PackedFile], files_data: &[StoredData], version: ::PFHVersion, bitmask: ::PFHFlags, content_position: u64) -> Result<()> {
+    if version.has_padding(bitmask) && content_position & 7 != 0 {
+        output_file.write_all(&vec![0; (8 - (content_position & 7)) as usize])?;
+    }
+    for (file, data) in files.iter().zip(files_data) {
+        match *data {
+            StoredData::Memory(ref data) => {
+                if bitmask.contains(::PFHFlags::HAS_ENCRYPTED_CONTENT) {
+                    let ciphertext = ::crypto::encrypt_file(data);
+                    if version.has_padding(bitmask) {
+                        output_file.write_all(&ciphertext)?;
+                    } else {
+                        output_file.write_all(&ciphertext[..data.len()])?;
+                    }
+                } else {
+                    output_file.write_all(data)?;
+                }
+            },
+
+            // We copy it in chunks, so big PackedFiles are never fully loaded in memory.
+            StoredData::Raw(ref lazy, ref range) => {
+                let mut position = range.start;
+                while position < range.end {
+                    let end = cmp::min(position + COPY_CHUNK_SIZE, range.end);
+                    output_file.write_all(&file.with_path(lazy.file_view.read(&(position..end)))?)?;
+                    position = end;
+                }
+            }
+        }
     }
     Ok(())
 }
 
-pub fn build_pack_from_filesystem(input_directory: &Path, output_file: &mut File, version: ::PFHVersion, bitmask: ::PFHFlags, file_type: ::PFHFileType, pfh_timestamp: u32, pack_files: &[String]) -> Result<()> {
-    let mut input_files = traverse_directory(input_directory, "")?;
-    build_pack_from_memory(pack_files, &mut input_files, output_file, version, bitmask, file_type, pfh_timestamp)
+/// This function checks the settings of the builder and his PackedFiles, so we don't write broken PackFiles.
+fn validate_builder(builder: &::PackBuilder) -> Result<()> {
+    let version = builder.version;
+    let bitmask = builder.bitmask;
+    version.check_bitmask(bitmask)?;
+    if !version.has_index_compression_byte(bitmask) {
+        if builder.compression != ::PFHCompressionPolicy::None && builder.compression != ::PFHCompressionPolicy::PerPackedFile {
+            return Err(Error::UnsupportedCompression { version })
+        }
+        if builder.packed_files.iter().any(|packed_file| builder.compression.should_compress(packed_file)) {
+            return Err(Error::UnsupportedCompression { version })
+        }
+    }
+    if version.get_header_timestamp_size() == 4 && builder.timestamp > u64::from(u32::MAX) {
+        return Err(Error::UnsupportedTimestamp { version, timestamp: builder.timestamp })
+    }
+    if version == ::PFHVersion::PFH6 {
+        if let Some(ref subheader) = builder.subheader {
+            check_subheader(subheader)?;
+        }
+    }
+
+    let mut paths = HashSet::with_capacity(builder.packed_files.len());
+    for packed_file in &builder.packed_files {
+        if version.get_index_timestamp_size(bitmask) == 4 {
+            if let Some(timestamp) = packed_file.timestamp {
+                if timestamp > u64::from(u32::MAX) {
+                    return Err(Error::PackedFileError { path: packed_file.path.clone(), source: Box::new(Error::UnsupportedTimestamp { version, timestamp }) })
+                }
+            }
+        }
+        if packed_file.path.is_empty() {
+            return Err(Error::InvalidPathError { path: packed_file.path.clone(), reason: "the path is empty" })
+        }
+        if packed_file.path.contains('\0') {
+            return Err(Error::InvalidPathError { path: packed_file.path.clone(), reason: "the path contains a null character" })
+        }
+        if !paths.insert(::parse::normalize_path(&packed_file.path).to_lowercase()) {
+            return Err(Error::InvalidPathError { path: packed_file.path.clone(), reason: "there is another PackedFile with the same path" })
+        }
+    }
+    for pack_file in &builder.pack_files {
+        if pack_file.contains('\0') {
+            return Err(Error::InvalidPathError { path: pack_file.clone(), reason: "the PackFile name contains a null character" })
+        }
+    }
+    Ok(())
 }
 
-pub fn build_pack_from_memory<P: Borrow<::PackedFile>>(
-    pack_files: &[String],
-    packed_files: &mut Vec<P>,
-    output_file: &mut File,
-    version: ::PFHVersion,
-    bitmask: ::PFHFlags,
-    file_type: ::PFHFileType,
-    pfh_timestamp: u32
-) -> Result<()> {
+fn write_pack<W: Write>(builder: &::PackBuilder, packed_files: &[&::PackedFile], output_file: &mut W) -> Result<()> {
+    let version = builder.version;
+    let bitmask = builder.bitmask;
+    let pack_files = &builder.pack_files;
 
     let mut pack_file_index_size = 0;
     for pack_file in pack_files {
-        pack_file_index_size += pack_file.len() + 1;
+        pack_file_index_size += pack_file.len() as u64 + 1;
     }
 
     let mut packed_file_index_size = 0;
-    packed_files.sort_unstable_by(|a, b| a.borrow().path.cmp(&b.borrow().path));
-    let packed_files = &*packed_files;
     for packed_file in packed_files {
-        let packed_file: &::PackedFile = packed_file.borrow();
-        packed_file_index_size += packed_file.path.len() as u32 + 1;
+        packed_file_index_size += packed_file.path.len() as u64 + 1;
         packed_file_index_size += 4;
-        if bitmask.contains(::PFHFlags::HAS_INDEX_WITH_TIMESTAMPS) {
-            packed_file_index_size += 4;
-        }
-        if version == ::PFHVersion::PFH5 && !bitmask.contains(::PFHFlags::HAS_BIG_HEADER) {
+        packed_file_index_size += u64::from(version.get_index_timestamp_size(bitmask));
+        if version.has_index_compression_byte(bitmask) {
             packed_file_index_size += 1;
         }
     }
-    write_header(output_file, version, bitmask, file_type, pfh_timestamp, pack_file_index_size as u32, packed_file_index_size, pack_files, &packed_files)?;
+    let packed_files_compressed = packed_files.iter().map(|packed_file| builder.compression.should_compress(packed_file)).collect::<Vec<_>>();
+    let packed_files_data = packed_files.iter().zip(&packed_files_compressed).map(|(packed_file, compress)| get_stored_data(packed_file, *compress, version, bitmask)).collect::<Result<Vec<_>>>()?;
+    let packed_files_sizes = packed_files.iter().zip(&packed_files_data).map(|(packed_file, data)| packed_file.with_path(get_u32_size(data.len()))).collect::<Result<Vec<_>>>()?;
+
+    // The signature goes right after the content, so we need to know where the content ends before writing the header.
+    let content_position = version.get_header_size(bitmask) as u64 + pack_file_index_size + packed_file_index_size;
+    let mut signature_offset = if version.has_padding(bitmask) { (content_position + 7) & !7 } else { content_position };
+    for data in &packed_files_data {
+        signature_offset += get_stored_size(data, version, bitmask);
+    }
+
+    // Only PackFiles with a big header store the signature offset, so bigger PackFiles are fine without one.
+    let signature_offset = if bitmask.contains(::PFHFlags::HAS_BIG_HEADER) { get_u32_size(signature_offset)? } else { 0 };
+    write_header(output_file, builder, get_u32_size(pack_file_index_size)?, get_u32_size(packed_file_index_size)?, signature_offset)?;
     write_pack_file_index(output_file, pack_files)?;
-    write_packed_file_index(output_file, packed_files, version, bitmask)?;
-    write_content(output_file, packed_files)?;
+    write_packed_file_index(output_file, packed_files, &packed_files_sizes, &packed_files_compressed, version, bitmask)?;
+    write_content(output_file, packed_files, &packed_files_data, version, bitmask, content_position)?;
+    if bitmask.contains(::PFHFlags::HAS_BIG_HEADER) {
+        if let Some(ref big_header) = builder.big_header {
+            output_file.write_all(&big_header.signature)?;
+        }
+    }
     Ok(())
 }
+
+/// This function returns the path of a file next to `path`, with the same name plus the provided prefix and suffix.
+fn get_sibling_path(path: &Path, prefix: &str, suffix: &str) -> Result<PathBuf> {
+    match path.file_name() {
+        Some(file_name) => Ok(path.with_file_name(format!("{}{}{}", prefix, file_name.to_string_lossy(), suffix))),
+        None => Err(Error::InvalidPathError { path: path.to_string_lossy().into_owned(), reason: "the path doesn't point to a file" })
+    }
+}
+
+/// This function creates a new temporary file next to `path`. Its name is unique between processes and threads,
+/// and it's never an existing file, so concurrent saves can't write into each other's temporary files.
+fn create_temp_file(path: &Path) -> Result<(PathBuf, File)> {
+    loop {
+        let suffix = format!(".{}.{}.tmp", process::id(), TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed));
+        let temp_path = get_sibling_path(path, ".", &suffix)?;
+        match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(ref error) if error.kind() == ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error.into())
+        }
+    }
+}
+
+/// This function syncs the directory containing `path`, so a rename into it survives a crash.
+#[cfg(unix)]
+fn sync_parent_directory(path: &Path) -> Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new(".")
+    };
+    File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+/// Directories can't be opened like files outside of unix, and renames are already durable on the filesystems we care about there.
+#[cfg(not(unix))]
+fn sync_parent_directory(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// This function writes a file using `write` without ever leaving it half-written: everything goes to a temporary file next to it,
+/// which is synced to disk and then renamed over `path`. If `backup` is true, the previous file is kept as `<path>.bak`.
+///
+/// `path` is only replaced at the end, so it can be the PackFile the data is being lazily read from. Open files keep pointing
+/// to the old one.
+fn write_file_atomically<F>(path: &Path, backup: bool, write: F) -> Result<()>
+    where F: FnOnce(&mut BufWriter<&File>) -> Result<()> {
+    let (temp_path, file) = create_temp_file(path)?;
+    let result = (|| -> Result<()> {
+        {
+            let mut writer = BufWriter::new(&file);
+            write(&mut writer)?;
+            writer.flush()?;
+        }
+        file.sync_all()?;
+        if backup && path.exists() {
+            let backup_path = get_sibling_path(path, "", ".bak")?;
+            if backup_path.exists() {
+                fs::remove_file(&backup_path)?;
+            }
+            // A hard link keeps the old file without copying it, but not every filesystem supports them.
+            if fs::hard_link(path, &backup_path).is_err() {
+                fs::copy(path, &backup_path)?;
+            }
+        }
+        fs::rename(&temp_path, path)?;
+        sync_parent_directory(path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+impl ::PackBuilder {
+
+    /// This function creates a `PackBuilder` for a PackFile of the provided version and type, with the default settings.
+    pub fn new(version: ::PFHVersion, file_type: ::PFHFileType) -> Self {
+        ::PackBuilder {
+            version,
+            file_type,
+            bitmask: ::PFHFlags::empty(),
+            timestamp: 0,
+            pack_files: vec!(),
+            subheader: None,
+            big_header: None,
+            compression: ::PFHCompressionPolicy::None,
+            packed_files: vec!()
+        }
+    }
+
+    /// This function sets the [`PFHFlags`](struct.PFHFlags.html) of the PackFile.
+    pub fn set_bitmask(&mut self, bitmask: ::PFHFlags) -> &mut Self {
+        self.bitmask = bitmask;
+        self
+    }
+
+    /// This function sets the timestamp of the header of the PackFile. PFH0 PackFiles ignore it.
+    pub fn set_timestamp(&mut self, timestamp: u64) -> &mut Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// This function sets the [`PFHSubheader`](struct.PFHSubheader.html) of PFH6 PackFiles.
+    pub fn set_subheader(&mut self, subheader: ::PFHSubheader) -> &mut Self {
+        self.subheader = Some(subheader);
+        self
+    }
+
+    /// This function sets the [`PFHBigHeader`](struct.PFHBigHeader.html) of PackFiles with `HAS_BIG_HEADER`. His `signature_offset` is ignored,
+    /// as it depends on the content of the PackFile.
+    pub fn set_big_header(&mut self, big_header: ::PFHBigHeader) -> &mut Self {
+        self.big_header = Some(big_header);
+        self
+    }
+
+    /// This function sets the [`PFHCompressionPolicy`](enum.PFHCompressionPolicy.html) deciding what PackedFiles get compressed.
+    pub fn set_compression(&mut self, compression: ::PFHCompressionPolicy) -> &mut Self {
+        self.compression = compression;
+        self
+    }
+
+    /// This function adds a PackFile to the `PackFile Index`, the list of PackFiles the game loads before this one.
+    pub fn add_pack_file(&mut self, pack_file: &str) -> &mut Self {
+        self.pack_files.push(pack_file.to_owned());
+        self
+    }
+
+    /// This function adds a PackedFile. If it's lazy-loaded, his data is not loaded until `finish` is called.
+    pub fn add_packed_file(&mut self, packed_file: ::PackedFile) -> &mut Self {
+        self.packed_files.push(packed_file);
+        self
+    }
+
+    /// This function adds a PackedFile with the provided path and data.
+    pub fn add_bytes(&mut self, path: &str, data: Vec<u8>) -> &mut Self {
+        self.add_packed_file(::PackedFile::new(None, path.to_owned(), data))
+    }
+
+    /// This function adds a PackedFile with the provided path, and the data read from `reader` until its end.
+    pub fn add_reader<R: Read>(&mut self, path: &str, mut reader: R) -> Result<&mut Self> {
+        let mut data = vec!();
+        reader.read_to_end(&mut data)?;
+        Ok(self.add_bytes(path, data))
+    }
+
+    /// This function adds a PackedFile with the provided path, and the data of the file at `file_path`.
+    pub fn add_file(&mut self, path: &str, file_path: &Path) -> Result<&mut Self> {
+        self.add_reader(path, File::open(file_path)?)
+    }
+
+    /// This function adds every file inside `directory` and his subfolders as a PackedFile, using his path relative to `directory`.
+    pub fn add_directory(&mut self, directory: &Path) -> Result<&mut Self> {
+        let packed_files = traverse_directory(directory, "")?;
+        self.packed_files.extend(packed_files);
+        Ok(self)
+    }
+
+    /// This function checks that the settings and the PackedFiles of the builder can be written, without writing anything.
+    ///
+    /// It fails if the bitmask or the compression policy are not supported by the version, the Subheader is invalid,
+    /// a timestamp doesn't fit in the Index of the version, or a path is empty, contains a null character or it's repeated.
+    pub fn validate(&self) -> Result<()> {
+        validate_builder(self)
+    }
+
+    /// This function validates the builder and writes the PackFile into `output_file`, which can be anything implementing `Write`.
+    ///
+    /// PackedFiles are written sorted by path, like the game expects. Nothing is written if the validation fails.
+    pub fn finish<W: Write>(self, output_file: &mut W) -> Result<()> {
+        validate_builder(&self)?;
+        self.write(output_file)
+    }
+
+    /// This function validates the builder and writes the PackFile into the file at `path`, replacing it atomically.
+    ///
+    /// The PackFile is written to a temporary file next to `path`, which is synced to disk and renamed over `path` once complete,
+    /// so a failure or a crash never leaves a half-written PackFile. `path` can be the PackFile the PackedFiles are being read from.
+    /// If `backup` is true, the previous file is kept as `<path>.bak`.
+    pub fn save(self, path: &Path, backup: bool) -> Result<()> {
+        validate_builder(&self)?;
+        write_file_atomically(path, backup, |output_file| self.write(output_file))
+    }
+
+    fn write<W: Write>(&self, output_file: &mut W) -> Result<()> {
+        let mut packed_files = self.packed_files.iter().collect::<Vec<&::PackedFile>>();
+        packed_files.sort_by(|a, b| a.path.cmp(&b.path));
+        write_pack(self, &packed_files, output_file)
+    }
+}
+
+pub fn build_pack_from_filesystem<W: Write>(input_directory: &Path, output_file: &mut W, version: ::PFHVersion, bitmask: ::PFHFlags, file_type: ::PFHFileType, pfh_timestamp: u64, pack_files: &[String]) -> Result<()> {
+    let input_files = traverse_directory(input_directory, "")?;
+    build_pack_from_memory(pack_files, &input_files, output_file, version, bitmask, file_type, pfh_timestamp)
+}
+
+pub fn build_pack_from_memory<W: Write, P: Borrow<::PackedFile>>(pack_files: &[String], packed_files: &[P], output_file: &mut W, version: ::PFHVersion, bitmask: ::PFHFlags, file_type: ::PFHFileType, pfh_timestamp: u64) -> Result<()> {
+    let mut builder = ::PackBuilder::new(version, file_type);
+    builder.set_bitmask(bitmask).set_timestamp(pfh_timestamp).set_compression(::PFHCompressionPolicy::PerPackedFile);
+    for pack_file in pack_files {
+        builder.add_pack_file(pack_file);
+    }
+    for packed_file in packed_files {
+        builder.add_packed_file(packed_file.borrow().clone());
+    }
+    builder.finish(output_file)
+}
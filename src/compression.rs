@@ -0,0 +1,57 @@
+use std::convert::TryFrom;
+use std::io::Read;
+use byteorder::ByteOrder;
+use byteorder::LittleEndian;
+use byteorder::WriteBytesExt;
+use xz2::read::XzDecoder;
+use xz2::read::XzEncoder;
+use xz2::stream::LzmaOptions;
+use xz2::stream::Stream;
+
+use error::{Error, Result};
+
+/// Size of the properties at the start of a LZMA stream.
+const LZMA_PROPERTIES_SIZE: usize = 5;
+
+/// Size of the header of a `.lzma` stream: the properties, followed by the decompressed size as an `u64`.
+const LZMA_ALONE_HEADER_SIZE: usize = LZMA_PROPERTIES_SIZE + 8;
+
+/// Compressed PackedFiles start with the decompressed size as an `u32`, followed by a `.lzma` stream without his own
+/// decompressed size. We put it back in its place, so liblzma can decode the stream.
+pub fn decompress_data(ciphertext: &[u8]) -> Result<Vec<u8>> {
+    if ciphertext.len() < 4 + LZMA_PROPERTIES_SIZE {
+        return Err(Error::CompressionError(format!("compressed data too short ({} bytes)", ciphertext.len())))
+    }
+    let decompressed_size = LittleEndian::read_u32(&ciphertext[0..4]);
+    let mut stream = Vec::with_capacity(ciphertext.len() + 4);
+    stream.extend_from_slice(&ciphertext[4..4 + LZMA_PROPERTIES_SIZE]);
+    stream.write_u64::<LittleEndian>(decompressed_size as u64)?;
+    stream.extend_from_slice(&ciphertext[4 + LZMA_PROPERTIES_SIZE..]);
+
+    let decoder = Stream::new_lzma_decoder(u64::MAX).map_err(|error| Error::CompressionError(error.to_string()))?;
+    // The decompressed size comes from the PackFile, so we don't trust it for preallocating, and we stop decoding past it.
+    let mut plaintext = vec!();
+    XzDecoder::new_stream(&stream[..], decoder).take(decompressed_size as u64 + 1).read_to_end(&mut plaintext)?;
+    if plaintext.len() != decompressed_size as usize {
+        return Err(Error::CompressionError(format!("expected {} decompressed bytes, got {}", decompressed_size, plaintext.len())))
+    }
+    Ok(plaintext)
+}
+
+/// This is the inverse of `decompress_data`: we encode a `.lzma` stream, and replace his decompressed size with the `u32` one the games expect.
+pub fn compress_data(plaintext: &[u8]) -> Result<Vec<u8>> {
+    let size = u32::try_from(plaintext.len()).map_err(|_| Error::UnsupportedSize { size: plaintext.len() as u64 })?;
+    let options = LzmaOptions::new_preset(3).map_err(|error| Error::CompressionError(error.to_string()))?;
+    let encoder = Stream::new_lzma_encoder(&options).map_err(|error| Error::CompressionError(error.to_string()))?;
+    let mut stream = vec!();
+    XzEncoder::new_stream(plaintext, encoder).read_to_end(&mut stream)?;
+    if stream.len() < LZMA_ALONE_HEADER_SIZE {
+        return Err(Error::CompressionError("liblzma returned a truncated stream".to_owned()))
+    }
+
+    let mut ciphertext = Vec::with_capacity(stream.len() - 4);
+    ciphertext.write_u32::<LittleEndian>(size)?;
+    ciphertext.extend_from_slice(&stream[..LZMA_PROPERTIES_SIZE]);
+    ciphertext.extend_from_slice(&stream[LZMA_ALONE_HEADER_SIZE..]);
+    Ok(ciphertext)
+}
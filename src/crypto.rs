@@ -18,13 +18,15 @@ pub fn decrypt_index_item_file_length(item_index: u32, ciphertext: u32) -> u32 {
     decrypted
 }
 
-pub fn decrypt_index_item_filename(ciphertext: &[u8], key: u8) -> (Vec<u8>, u32) {
+/// Returns `None` if the ciphertext ends before the null terminator.
+pub fn decrypt_index_item_filename(ciphertext: &[u8], key: u8) -> Option<(Vec<u8>, u32)> {
     let mut buffer: Vec<u8> = Vec::with_capacity(100);
     let mut idx = 0;
     loop {
-        let c = ciphertext[idx as usize] ^ !key ^ get_key_at(idx);
+        let encrypted = *ciphertext.get(idx)?;
+        let c = encrypted ^ !key ^ get_key_at(idx);
         if ::DEBUG {
-            println!("{:X} ({}) = {:X} ^ {:X} ^ {:X}", c, c as char, ciphertext[idx as usize], !key, get_key_at(idx));
+            println!("{:X} ({}) = {:X} ^ {:X} ^ {:X}", c, c as char, encrypted, !key, get_key_at(idx));
         }
         idx += 1;
         if c == 0 {
@@ -32,15 +34,28 @@ pub fn decrypt_index_item_filename(ciphertext: &[u8], key: u8) -> (Vec<u8>, u32)
         }
         buffer.push(c);
     }
-    (buffer, idx as u32)
+    Some((buffer, idx as u32))
+}
+
+pub fn encrypt_index_item_file_length(item_index: u32, plaintext: u32) -> u32 {
+    // The cipher is a plain XOR, so encrypting is the same as decrypting.
+    decrypt_index_item_file_length(item_index, plaintext)
+}
+
+pub fn encrypt_index_item_filename(plaintext: &[u8], key: u8) -> Vec<u8> {
+    let mut buffer: Vec<u8> = Vec::with_capacity(plaintext.len() + 1);
+    for (idx, c) in plaintext.iter().chain(&[0]).enumerate() {
+        buffer.push(c ^ !key ^ get_key_at(idx));
+    }
+    buffer
 }
 
 static FILE_KEY: Wrapping<u64> = Wrapping(0x8FEB2A6740A6920E);
 
 pub fn decrypt_file(ciphertext: &[u8], length: usize, verbose: bool) -> Vec<u8> {
     let mut plaintext = Vec::with_capacity(ciphertext.len());
-    let padded_length = ciphertext.len() + 7 & !7;
-    assert!(padded_length % 8 == 0);
+    let padded_length = (ciphertext.len() + 7) & !7;
+    assert!(padded_length & 7 == 0);
     assert!(padded_length < ciphertext.len() + 8);
     let mut edi: u32 = 0;
     let mut esi = 0;
@@ -66,16 +81,16 @@ pub fn decrypt_file(ciphertext: &[u8], length: usize, verbose: bool) -> Vec<u8>
         }
 
         // xor eax, [ebx+esi]
-        eax ^= read_padded_u32(&ciphertext, esi..esi + 4);
+        eax ^= read_padded_u32(ciphertext, esi..esi + 4);
         if verbose {
             println!("eax: {:X}", eax);
         }
 
         // add edi, 8
-        edi += 8;
+        edi = edi.wrapping_add(8);
 
         // xor edx, [ebx+esi+4]
-        let _edx = read_padded_u32(&ciphertext, esi + 4..esi + 8);
+        let _edx = read_padded_u32(ciphertext, esi + 4..esi + 8);
         if verbose {
             println!("_edx {:X}", _edx);
         }
@@ -98,6 +113,13 @@ pub fn decrypt_file(ciphertext: &[u8], length: usize, verbose: bool) -> Vec<u8>
     plaintext
 }
 
+pub fn encrypt_file(plaintext: &[u8]) -> Vec<u8> {
+    // Same as with the index, encrypting is the same as decrypting. We keep the whole last block,
+    // so PackFiles with padding can store it.
+    let padded_length = (plaintext.len() + 7) & !7;
+    decrypt_file(plaintext, padded_length, false)
+}
+
 fn read_padded_u32(source: &[u8], range: Range<usize>) -> u32 {
     if range.end <= source.len() {
         LittleEndian::read_u32(&source[range])
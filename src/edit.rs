@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use error::{Error, Result};
+use parse::normalize_path;
+
+/// This function returns the key of a path in the PackedFiles of an `EditablePackFile`, so paths the game considers equal get the same key.
+fn get_key(path: &str) -> String {
+    normalize_path(path).to_lowercase()
+}
+
+impl ::EditablePackFile {
+
+    /// This function creates an `EditablePackFile` with the settings and the PackedFiles of the provided PackFile.
+    /// The data of the PackedFiles is not loaded.
+    ///
+    /// It fails if the PackedFile Index of the PackFile is malformed, so no PackedFile is silently lost when saving it.
+    pub fn new(pack_file: &::PackFile) -> Result<Self> {
+        let mut packed_files = BTreeMap::new();
+        for packed_file in pack_file.entries() {
+            let packed_file = packed_file?;
+            packed_files.insert(get_key(&packed_file.path), packed_file);
+        }
+        Ok(::EditablePackFile {
+            path: None,
+            backup: false,
+            header: pack_file.get_header().clone(),
+            pack_files: pack_file.get_pack_file_index(),
+            packed_files
+        })
+    }
+
+    /// This function opens the PackFile at `path` as an `EditablePackFile`. `save` writes it back to the same path.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut pack_file = Self::new(&::parse_pack(File::open(path)?)?)?;
+        pack_file.path = Some(path.to_path_buf());
+        Ok(pack_file)
+    }
+
+    /// This function returns the header of the original PackFile. The sizes of his indexes are not updated by the edits.
+    pub fn get_header(&self) -> &::PackHeader {
+        &self.header
+    }
+
+    /// This function returns the path `save` writes the PackFile to, if it has one.
+    pub fn get_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// This function sets if saving the PackFile keeps the previous file as `<path>.bak`. It doesn't by default.
+    pub fn set_backup(&mut self, backup: bool) -> &mut Self {
+        self.backup = backup;
+        self
+    }
+
+    /// This function returns the PackedFile with the provided path, if the PackFile contains it.
+    pub fn get(&self, path: &str) -> Option<&::PackedFile> {
+        self.packed_files.get(&get_key(path))
+    }
+
+    /// This function returns if the PackFile contains a PackedFile with the provided path.
+    pub fn contains(&self, path: &str) -> bool {
+        self.packed_files.contains_key(&get_key(path))
+    }
+
+    /// This function returns an iterator over the PackedFiles of the PackFile, sorted by path.
+    pub fn iter(&self) -> impl Iterator<Item = &::PackedFile> {
+        self.packed_files.values()
+    }
+
+    /// This function inserts the provided PackedFile. If there was already a PackedFile with the same path, it's replaced and returned.
+    pub fn insert(&mut self, packed_file: ::PackedFile) -> Option<::PackedFile> {
+        self.packed_files.insert(get_key(&packed_file.path), packed_file)
+    }
+
+    /// This function removes the PackedFile with the provided path, and returns it.
+    pub fn remove(&mut self, path: &str) -> Option<::PackedFile> {
+        self.packed_files.remove(&get_key(path))
+    }
+
+    /// This function changes the path of a PackedFile, without loading his data.
+    ///
+    /// It fails if there is no PackedFile at `path`, or if there is already another PackedFile at `new_path`.
+    pub fn rename(&mut self, path: &str, new_path: &str) -> Result<()> {
+        let key = get_key(path);
+        let new_key = get_key(new_path);
+        if !self.packed_files.contains_key(&key) {
+            return Err(Error::InvalidPathError { path: path.to_owned(), reason: "there is no PackedFile with this path" })
+        }
+        if new_key != key && self.packed_files.contains_key(&new_key) {
+            return Err(Error::InvalidPathError { path: new_path.to_owned(), reason: "there is another PackedFile with the same path" })
+        }
+        let mut packed_file = self.packed_files.remove(&key).unwrap();
+        packed_file.path = new_path.to_owned();
+        self.packed_files.insert(new_key, packed_file);
+        Ok(())
+    }
+
+    /// This function replaces the data of the PackedFile with the provided path, keeping his path, timestamp and compression.
+    ///
+    /// It fails if there is no PackedFile at `path`.
+    pub fn replace(&mut self, path: &str, data: Vec<u8>) -> Result<()> {
+        match self.packed_files.get_mut(&get_key(path)) {
+            Some(packed_file) => {
+                packed_file.set_data(Arc::new(data));
+                Ok(())
+            },
+            None => Err(Error::InvalidPathError { path: path.to_owned(), reason: "there is no PackedFile with this path" })
+        }
+    }
+
+    /// This function writes the edited PackFile into `output_file`, which can be anything implementing `Write`.
+    ///
+    /// Everything is validated like `PackBuilder::finish` does before writing anything.
+    pub fn write<W: Write>(&self, output_file: &mut W) -> Result<()> {
+        self.get_builder().finish(output_file)
+    }
+
+    /// This function returns a `PackBuilder` with the settings and the PackedFiles of the PackFile.
+    fn get_builder(&self) -> ::PackBuilder {
+        let mut builder = ::PackBuilder::new(self.header.version, self.header.file_type);
+        builder.set_bitmask(self.header.bitmask)
+            .set_timestamp(self.header.timestamp)
+            .set_compression(::PFHCompressionPolicy::PerPackedFile);
+        if let Some(ref subheader) = self.header.subheader {
+            builder.set_subheader(subheader.clone());
+        }
+        if let Some(ref big_header) = self.header.big_header {
+            builder.set_big_header(big_header.clone());
+        }
+        for pack_file in &self.pack_files {
+            builder.add_pack_file(pack_file);
+        }
+        for packed_file in self.packed_files.values() {
+            builder.add_packed_file(packed_file.clone());
+        }
+        builder
+    }
+
+    /// This function writes the edited PackFile back to his path. It fails with `Error::MissingPathError` if it doesn't have one.
+    pub fn save(&mut self) -> Result<()> {
+        let path = self.path.clone().ok_or(Error::MissingPathError)?;
+        self.save_as(&path)
+    }
+
+    /// This function writes the edited PackFile to `path`, which becomes the path used by `save`.
+    ///
+    /// The file is replaced atomically, like `PackBuilder::save` does, so `path` can be the PackFile this one was opened from
+    /// without loading the data of his PackedFiles first.
+    pub fn save_as(&mut self, path: &Path) -> Result<()> {
+        self.get_builder().save(path, self.backup)?;
+        self.path = Some(path.to_path_buf());
+        Ok(())
+    }
+}
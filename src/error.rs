@@ -1,5 +1,7 @@
 //! This module contains all error-related code of the library.
 
+use std::error;
+use std::fmt;
 use std::io;
 use std::result;
 use cached_file_view::FileViewError;
@@ -11,30 +13,108 @@ pub type Result<T> = result::Result<T, Error>;
 /// 
 /// The possible variants, or error types are:
 /// - `UnsupportedPackFile`: Used for when we try to open explicity unsupported PackFiles, like PackFiles from games we don't support yet.
-/// - `InvalidHeaderError`: Used for when the Header of the PackFile is not valid.
-/// - `InvalidFileError`: Used for when the File we are trying to open is not a valid PackFile. 
-/// - `IndexIteratorError`: Used when iterating through PackedFiles fails for any reason.
-/// - `IOError`: Used for generic IO errors.
+/// - `InvalidHeaderError`: Used for when the Header of the PackFile is not valid. It contains what's wrong with it.
+/// - `InvalidFileError`: Used for when the File we are trying to open is not a valid PackFile. It contains what's wrong with it.
+/// - `IndexEntryError`: Used when an entry of the PackedFile Index is malformed. It contains the position of the entry in the Index, his offset in bytes from the start of the Index, and what's wrong with it.
+/// - `PackedFileError`: Used when reading or writing the data of a PackedFile fails. It contains the path of the PackedFile, and the error that caused it.
+/// - `InvalidSubheaderError`: Used for when we try to build a PFH6 PackFile with a Subheader that doesn't fit in his fixed-size fields. It contains what's wrong with it.
+/// - `UnsupportedBitmask`: Used for when a PackFile has, or we try to build one with, a bitmask its version doesn't support. It contains the version, and the unsupported flags.
+/// - `UnsupportedTimestamp`: Used for when we try to build a PackFile with a timestamp too big for the fields of its version. It contains the version, and the timestamp.
+/// - `UnsupportedSize`: Used for when we try to build a PackFile with a size or an offset that doesn't fit in its 32 bits fields. It contains the size.
+/// - `CompressionError`: Used when compressing or decompressing the data of a PackedFile fails. It contains what went wrong. Errors liblzma returns while reading a stream are kept as `IOError`.
+/// - `UnsupportedCompression`: Used for when we try to build a PackFile with compressed PackedFiles, and its version doesn't support them. It contains the version.
+/// - `InvalidPathError`: Used for when the path of a PackedFile, or the name of a PackFile in the PackFile Index, can't be written. It contains the path, and what's wrong with it.
+/// - `MissingPathError`: Used for when we try to `save` an editable PackFile that was not opened from, or saved to, a file.
+/// - `InvalidPatternError`: Used for when a glob or regex pattern to find PackedFiles is not valid. It contains what's wrong with it.
+/// - `FileViewError`: Used when reading from a PackFile fails. It contains the underlying `FileViewError`.
+/// - `IOError`: Used for generic IO errors. It contains the underlying `io::Error`.
 #[derive(Debug)]
 pub enum Error {
     UnsupportedPackFile,
-    InvalidHeaderError,
-    InvalidFileError,
-    IndexIteratorError,
-    IOError
+    InvalidHeaderError(String),
+    InvalidFileError(String),
+    IndexEntryError {
+        entry: u32,
+        offset: u64,
+        reason: &'static str
+    },
+    PackedFileError {
+        path: String,
+        source: Box<Error>
+    },
+    InvalidSubheaderError(String),
+    UnsupportedBitmask {
+        version: ::PFHVersion,
+        flags: ::PFHFlags
+    },
+    UnsupportedTimestamp {
+        version: ::PFHVersion,
+        timestamp: u64
+    },
+    UnsupportedSize {
+        size: u64
+    },
+    CompressionError(String),
+    UnsupportedCompression {
+        version: ::PFHVersion
+    },
+    InvalidPathError {
+        path: String,
+        reason: &'static str
+    },
+    MissingPathError,
+    InvalidPatternError(String),
+    FileViewError(FileViewError),
+    IOError(io::Error)
+}
+
+//--------------------------------------------------------------------------------//
+//                       Display and std::error::Error for Error
+//--------------------------------------------------------------------------------//
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnsupportedPackFile => write!(f, "unsupported PackFile"),
+            Error::InvalidHeaderError(ref reason) => write!(f, "invalid PackFile header: {}", reason),
+            Error::InvalidFileError(ref reason) => write!(f, "invalid PackFile: {}", reason),
+            Error::IndexEntryError { entry, offset, reason } => write!(f, "malformed PackedFile Index entry {} at offset 0x{:x} of the Index: {}", entry, offset, reason),
+            Error::PackedFileError { ref path, ref source } => write!(f, "error in PackedFile \"{}\": {}", path, source),
+            Error::InvalidSubheaderError(ref reason) => write!(f, "invalid Subheader: {}", reason),
+            Error::UnsupportedBitmask { version, flags } => write!(f, "{:?} PackFiles don't support the flags {:?}", version, flags),
+            Error::UnsupportedTimestamp { version, timestamp } => write!(f, "{:?} PackFiles can't store the timestamp {}, as it doesn't fit in 32 bits", version, timestamp),
+            Error::UnsupportedSize { size } => write!(f, "PackFiles can't store the size {}, as it doesn't fit in 32 bits", size),
+            Error::CompressionError(ref reason) => write!(f, "compression error: {}", reason),
+            Error::UnsupportedCompression { version } => write!(f, "{:?} PackFiles don't support compressed PackedFiles", version),
+            Error::InvalidPathError { ref path, reason } => write!(f, "invalid path \"{}\": {}", path, reason),
+            Error::MissingPathError => write!(f, "the PackFile has no path to be saved to"),
+            Error::InvalidPatternError(ref reason) => write!(f, "invalid pattern: {}", reason),
+            Error::FileViewError(ref error) => write!(f, "error reading the PackFile: {:?}", error),
+            Error::IOError(ref error) => write!(f, "I/O error: {}", error)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::PackedFileError { ref source, .. } => Some(&**source),
+            Error::IOError(ref error) => Some(error),
+            _ => None
+        }
+    }
 }
 
 //--------------------------------------------------------------------------------//
 //                       From<T> Implementations for Error
 //--------------------------------------------------------------------------------//
 impl From<FileViewError> for Error {
-    fn from(_: FileViewError) -> Self {
-        Error::IOError
+    fn from(error: FileViewError) -> Self {
+        Error::FileViewError(error)
     }
 }
 
 impl From<io::Error> for Error {
-    fn from(_: io::Error) -> Self {
-        Error::IOError
+    fn from(error: io::Error) -> Self {
+        Error::IOError(error)
     }
 }
@@ -4,16 +4,19 @@
 //! This library allows you to *open* those PackFiles and manipulate them however you want.
 //! 
 //! Not all Modern Total War games are supported yet. The supported ones are:
+//! - Pharaoh.
+//! - Warhammer 3.
+//! - Troy.
 //! - Warhammer 2.
 //! - Warhammer.
 //! - Attila.
 //! - Rome 2.
 //! - Arena.
-//!
-//! Games that will be supported in the future are:
 //! - Shogun 2.
 //! - Napoleon.
 //! - Empire.
+//!
+//! Games that will be supported in the future are:
 //! - Thrones of Brittania.
 //! - Three Kingdoms.
 //!
@@ -24,25 +27,39 @@
 extern crate bitflags;
 extern crate byteorder;
 extern crate cached_file_view;
+extern crate glob;
+extern crate regex;
+extern crate xz2;
 
 mod build;
+mod compression;
 mod crypto;
+mod edit;
 pub mod error;
 mod parse;
+mod source;
+mod tree;
 
-use error::Result;
+use error::{Error, Result};
+use parse::IndexError;
 use parse::LazyLoadingPackedFile;
+use parse::PackIndexEntry;
+use parse::PathIndex;
 
 use std::borrow::Borrow;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::fs::File;
+use std::io::{Read, Seek, Write};
 use std::fmt;
 use std::path::Path;
+use std::path::PathBuf;
 use cached_file_view::FileView;
-use cached_file_view::FileViewMapping;
+use source::PackSource;
 
 static DEBUG: bool = false;
+const PFH6_PREAMBLE: u32 = 0x36484650;
 const PFH5_PREAMBLE: u32 = 0x35484650;
 const PFH4_PREAMBLE: u32 = 0x34484650;
 const PFH3_PREAMBLE: u32 = 0x33484650;
@@ -53,6 +70,7 @@ const FILE_TYPE_RELEASE: u32    = 1;
 const FILE_TYPE_PATCH: u32      = 2;
 const FILE_TYPE_MOD: u32        = 3;
 const FILE_TYPE_MOVIE: u32      = 4;
+const SUBHEADER_MARK: u32 = 0x12345678;
 
 bitflags! {
 
@@ -74,12 +92,20 @@ bitflags! {
 /// This enum represents the **Version** of a PackFile.
 ///
 /// The possible values are:
+/// - `PFH6`: Used in Troy, Warhammer 3 and Pharaoh.
 /// - `PFH5`: Used in Warhammer 2 and Arena.
 /// - `PFH4`: Used in Warhammer 1, Attila, Rome 2, and Thrones of Brittania.
+/// - `PFH3`: Used in Shogun 2.
+/// - `PFH2`: Used in early Shogun 2 PackFiles.
+/// - `PFH0`: Used in Napoleon and Empire.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PFHVersion {
+    PFH6,
     PFH5,
     PFH4,
+    PFH3,
+    PFH2,
+    PFH0,
 }
 
 /// This enum represents the **Type** of a PackFile. 
@@ -101,6 +127,99 @@ pub enum PFHFileType {
     Other(u32),
 }
 
+/// This enum represents the **Compression Policy** used when building a PackFile.
+///
+/// The possible policies are:
+/// - `None`: no PackedFile gets compressed.
+/// - `PerPackedFile`: only the PackedFiles with `is_compressed` set get compressed.
+/// - `Extensions(Vec<String>)`: only the PackedFiles with one of these extensions (like `lua`, without the dot) get compressed. Case-insensitive.
+/// - `All`: all PackedFiles get compressed.
+///
+/// Only PFH5 PackFiles without big header and PFH6 PackFiles support compression. Building other PackFiles with any policy that compresses something fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PFHCompressionPolicy {
+    None,
+    PerPackedFile,
+    Extensions(Vec<String>),
+    All,
+}
+
+/// This struct represents the **Subheader** PFH6 PackFiles have after the usual header.
+///
+/// It contains:
+/// - `version`: the version of the Subheader itself. Always 1 in the PackFiles we've seen.
+/// - `game_version`: the version of the game this PackFile was made for.
+/// - `build_number`: the build number of the game this PackFile was made for.
+/// - `authoring_tool`: the name of the tool that made the PackFile, like `CA_TOOL`. Up to 8 bytes of UTF-8, without null characters.
+/// - `extra_data`: 256 bytes of data we don't know the meaning of yet. Usually all zeros.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PFHSubheader {
+    pub version: u32,
+    pub game_version: u32,
+    pub build_number: u32,
+    pub authoring_tool: String,
+    pub extra_data: Vec<u8>
+}
+
+/// This struct represents the extra data PackFiles with `HAS_BIG_HEADER` have in their header, and the signature at the end of them. Used in Arena.
+///
+/// It contains:
+/// - `unknown_1`, `unknown_2`, `unknown_3`, `unknown_4`: the fields of the extended header we don't know the meaning of yet.
+/// - `signature_offset`: the offset of the signature at the end of the PackFile. It's recalculated when building a PackFile.
+/// - `signature`: the signature at the end of the PackFile. It goes from the `signature_offset` to the end of the file, up to 0x100 bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PFHBigHeader {
+    pub unknown_1: u32,
+    pub unknown_2: u32,
+    pub unknown_3: u32,
+    pub signature_offset: u32,
+    pub unknown_4: u32,
+    pub signature: Vec<u8>
+}
+
+/// This struct represents a pattern to find PackedFiles by their path.
+///
+/// Paths are always matched using `/` as separator, whatever separator the PackFile uses. It can be:
+/// - A glob pattern, like `db/*_tables/*` or `script/**/*.lua`. `*` doesn't match across folders, but `**` does.
+/// - A regular expression, like `^script/.*\.lua$`.
+#[derive(Debug, Clone)]
+pub struct PathPattern {
+    matcher: PathMatcher
+}
+
+#[derive(Debug, Clone)]
+enum PathMatcher {
+    Glob(glob::Pattern),
+    Regex(regex::Regex)
+}
+
+/// This struct represents the decoded **Header** of a PackFile.
+///
+/// It contains:
+/// - `version`: the [`PFHVersion`](enum.PFHVersion.html) of the PackFile.
+/// - `file_type`: the [`PFHFileType`](enum.PFHFileType.html) of the PackFile.
+/// - `bitmask`: the [`PFHFlags`](struct.PFHFlags.html) of the PackFile.
+/// - `pack_file_index_length`: the amount of items in the PackFile Index.
+/// - `pack_file_index_size`: the size in bytes of the PackFile Index.
+/// - `packed_file_index_length`: the amount of items in the PackedFile Index.
+/// - `packed_file_index_size`: the size in bytes of the PackedFile Index.
+/// - `timestamp`: the timestamp of the PackFile. 0 in PFH0 PackFiles, as they don't have one.
+/// - `subheader`: the [`PFHSubheader`](struct.PFHSubheader.html) of PFH6 PackFiles.
+/// - `big_header`: the [`PFHBigHeader`](struct.PFHBigHeader.html) of PFH5 PackFiles with `HAS_BIG_HEADER`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackHeader {
+    pub version: PFHVersion,
+    pub file_type: PFHFileType,
+    pub bitmask: PFHFlags,
+    pub pack_file_index_length: u32,
+    pub pack_file_index_size: u32,
+    pub packed_file_index_length: u32,
+    pub packed_file_index_size: u32,
+    pub timestamp: u64,
+    pub subheader: Option<PFHSubheader>,
+    pub big_header: Option<PFHBigHeader>
+}
+
 /// This struct represents a parsed `PackFile`.
 ///
 /// All his members are private. To obtain any data from it you have to use the provided getters.
@@ -108,22 +227,93 @@ pub enum PFHFileType {
 /// to populate your own `PackFile` struct with the info/data you need.
 #[derive(Clone)]
 pub struct PackFile {
-    view: FileView,
-    begin: FileViewMapping
+    view: PackSource,
+    header: PackHeader,
+    pack_file_index: Vec<String>,
+    index: Arc<Vec<PackIndexEntry>>,
+    index_error: Option<IndexError>,
+    paths: Arc<PathIndex>
+}
+
+/// This struct builds a PackFile from PackedFiles added one by one.
+///
+/// Create it with `PackBuilder::new`, change the settings you need, add the PackedFiles from memory, readers or the filesystem,
+/// and write the PackFile with `finish`. Everything is validated before writing anything. The default settings are:
+/// - No flags in the bitmask, and 0 as timestamp.
+/// - An empty `PackFile Index`.
+/// - The default Subheader and big header, if the PackFile needs them.
+/// - No compression.
+pub struct PackBuilder {
+    version: PFHVersion,
+    file_type: PFHFileType,
+    bitmask: PFHFlags,
+    timestamp: u64,
+    pack_files: Vec<String>,
+    subheader: Option<PFHSubheader>,
+    big_header: Option<PFHBigHeader>,
+    compression: PFHCompressionPolicy,
+    packed_files: Vec<PackedFile>
+}
+
+/// This struct represents a PackFile that can be edited, and then saved.
+///
+/// Create it from a parsed [`PackFile`](struct.PackFile.html) with `EditablePackFile::new`, or from a file with `EditablePackFile::open`.
+/// You can insert, remove, rename and replace PackedFiles, and the PackedFiles you don't touch keep being lazily loaded from their PackFile.
+/// Paths are compared like the game does: `/` and `\` are the same separator, and the case is ignored.
+///
+/// The header, the bitmask and the `PackFile Index` are kept from the original PackFile, and PackedFiles stay compressed if they were.
+/// Saving replaces the file atomically, and can keep the previous one as a backup.
+pub struct EditablePackFile {
+    path: Option<PathBuf>,
+    backup: bool,
+    header: PackHeader,
+    pack_files: Vec<String>,
+    packed_files: BTreeMap<String, PackedFile>
+}
+
+/// This struct represents a **Folder** of the virtual directory tree of a PackFile.
+///
+/// PackFiles only store a flat list of paths. This tree is built from them, without loading any data. It contains:
+/// - `name`: the name of the folder. Empty for the root folder.
+/// - `file_count`: the amount of PackedFiles in this folder and all his subfolders.
+/// - `size`: the total size in bytes of the PackedFiles in this folder and all his subfolders, like they're stored in the PackFile.
+/// - `folders`: the immediate subfolders of this folder, by name.
+/// - `files`: the PackedFiles directly inside this folder, by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackFolder {
+    pub name: String,
+    pub file_count: u32,
+    pub size: u64,
+    pub folders: BTreeMap<String, PackFolder>,
+    pub files: BTreeMap<String, PackFolderFile>
+}
+
+/// This struct represents a PackedFile in the directory tree of a PackFile. It contains:
+/// - `path`: the full path of the PackedFile, like it's in the PackFile.
+/// - `size`: the size in bytes of the PackedFile, like it's stored in the PackFile.
+/// - `is_compressed`: if the PackedFile is stored compressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackFolderFile {
+    pub path: String,
+    pub size: u64,
+    pub is_compressed: bool
 }
 
 /// This struct represents a **PackedFile**, a File contained inside a PackFile. 
 ///
 /// A PackedFile is a File contained inside a PackFile. It contains:
-/// - `timestamp`: a timestamp in `u32` format of the PackedFile, usually his `last modified` date. Optional.
+/// - `timestamp`: a timestamp of the PackedFile, usually his `last modified` date. Optional. PFH2 and PFH3 PackFiles store it as a 64 bits `FILETIME`, newer ones as a 32 bits value.
 /// - `path`: a path of type `a/b/c.whatever`. This is the *virtual* path of the PackedFile.
+/// - `is_compressed`: if the data of the PackedFile is stored compressed in the PackFile. Only PFH5 PackFiles without big header and PFH6 PackFiles can have compressed PackedFiles.
+///   The data you get from `get_data` is always decompressed.
 /// - `data`: a `Mutex<PackedFileData>` with the data to be contained in the PackedFile. Private. If you want to get/set it, use the dedicated methods.
 ///
-/// Keep in mind that other than decrypting the data if it's encrypted, the PackedFiles data is stored as it's in the PackFile.
+/// Keep in mind that other than decrypting and decompressing the data if it's encrypted or compressed, the PackedFiles data is stored as it's in the PackFile.
 /// If you want to decode it/process it/edit it in any way, use an specialized program like RPFM, or write your own code for it.
 pub struct PackedFile {
-    pub timestamp: Option<u32>,
+    pub timestamp: Option<u64>,
     pub path: String,
+    pub is_compressed: bool,
     data: Mutex<PackedFileData>
 }
 
@@ -151,6 +341,37 @@ impl PFHFileType {
             PFHFileType::Other(value) => value
         }
     }
+
+    /// This function returns the PackFile's **Type** corresponding to the provided `u32` value.
+    pub(crate) fn from_value(value: u32) -> Self {
+        match value {
+            FILE_TYPE_BOOT => PFHFileType::Boot,
+            FILE_TYPE_RELEASE => PFHFileType::Release,
+            FILE_TYPE_PATCH => PFHFileType::Patch,
+            FILE_TYPE_MOD => PFHFileType::Mod,
+            FILE_TYPE_MOVIE => PFHFileType::Movie,
+            value => PFHFileType::Other(value)
+        }
+    }
+}
+
+impl PFHCompressionPolicy {
+
+    /// This function returns if the provided PackedFile has to be compressed under this policy.
+    pub fn should_compress(&self, packed_file: &PackedFile) -> bool {
+        match *self {
+            PFHCompressionPolicy::None => false,
+            PFHCompressionPolicy::PerPackedFile => packed_file.is_compressed,
+            PFHCompressionPolicy::Extensions(ref extensions) => {
+                let file_name = packed_file.path.rsplit(['/', '\\']).next().unwrap_or("");
+                match file_name.rfind('.') {
+                    Some(position) => extensions.iter().any(|extension| extension.eq_ignore_ascii_case(&file_name[position + 1..])),
+                    None => false
+                }
+            },
+            PFHCompressionPolicy::All => true,
+        }
+    }
 }
 
 impl PFHVersion {
@@ -158,52 +379,275 @@ impl PFHVersion {
     /// This function returns the PackFile's **Preamble** or **Id** (his 4 first bytes) in `u32` format.
     pub(crate) fn get_preamble(&self) -> u32 {
         match *self {
+            PFHVersion::PFH6 => PFH6_PREAMBLE,
             PFHVersion::PFH5 => PFH5_PREAMBLE,
             PFHVersion::PFH4 => PFH4_PREAMBLE,
+            PFHVersion::PFH3 => PFH3_PREAMBLE,
+            PFHVersion::PFH2 => PFH2_PREAMBLE,
+            PFHVersion::PFH0 => PFH0_PREAMBLE,
+        }
+    }
+
+    /// This function returns the PackFile's **Version** corresponding to the provided **Preamble**, if any.
+    pub(crate) fn from_preamble(preamble: u32) -> Option<Self> {
+        match preamble {
+            PFH6_PREAMBLE => Some(PFHVersion::PFH6),
+            PFH5_PREAMBLE => Some(PFHVersion::PFH5),
+            PFH4_PREAMBLE => Some(PFHVersion::PFH4),
+            PFH3_PREAMBLE => Some(PFHVersion::PFH3),
+            PFH2_PREAMBLE => Some(PFHVersion::PFH2),
+            PFH0_PREAMBLE => Some(PFHVersion::PFH0),
+            _ => None
+        }
+    }
+
+    /// This function returns the flags PackFiles of this version can have in their bitmask. Only PFH5 PackFiles (Arena) can have
+    /// the extended header, legacy PackFiles know nothing about encryption, and PFH0 PackFiles don't even have timestamps.
+    pub fn get_supported_flags(&self) -> PFHFlags {
+        match *self {
+            PFHVersion::PFH6 | PFHVersion::PFH4 => PFHFlags::HAS_ENCRYPTED_INDEX | PFHFlags::HAS_INDEX_WITH_TIMESTAMPS | PFHFlags::HAS_ENCRYPTED_CONTENT,
+            PFHVersion::PFH5 => PFHFlags::all(),
+            PFHVersion::PFH3 | PFHVersion::PFH2 => PFHFlags::HAS_INDEX_WITH_TIMESTAMPS,
+            PFHVersion::PFH0 => PFHFlags::empty(),
+        }
+    }
+
+    /// This function checks that the provided bitmask only has flags PackFiles of this version support.
+    pub(crate) fn check_bitmask(&self, bitmask: PFHFlags) -> Result<()> {
+        let unsupported = bitmask - self.get_supported_flags();
+        if !unsupported.is_empty() {
+            return Err(Error::UnsupportedBitmask { version: *self, flags: unsupported })
+        }
+        Ok(())
+    }
+
+    /// This function returns if PackFiles of this version with the provided bitmask have the extended header used by Arena.
+    pub(crate) fn has_big_header(&self, bitmask: PFHFlags) -> bool {
+        *self == PFHVersion::PFH5 && bitmask.contains(PFHFlags::HAS_BIG_HEADER)
+    }
+
+    /// This function returns the size of the header of PackFiles of this version with the provided bitmask.
+    /// PFH6 PackFiles have a 0x118 bytes Subheader after the usual PFH5 header.
+    pub(crate) fn get_header_size(&self, bitmask: PFHFlags) -> u32 {
+        match *self {
+            PFHVersion::PFH6 => 0x134,
+            PFHVersion::PFH5 if self.has_big_header(bitmask) => 0x30,
+            PFHVersion::PFH5 | PFHVersion::PFH4 => 0x1C,
+            PFHVersion::PFH3 | PFHVersion::PFH2 => 0x20,
+            PFHVersion::PFH0 => 0x18,
+        }
+    }
+
+    /// This function returns if the content of PackFiles of this version with the provided bitmask, and every PackedFile in it, is aligned to 8 bytes.
+    pub(crate) fn has_padding(&self, bitmask: PFHFlags) -> bool {
+        (*self == PFHVersion::PFH6 || *self == PFHVersion::PFH5) && bitmask.contains(PFHFlags::HAS_ENCRYPTED_CONTENT)
+    }
+
+    /// This function returns if every PackedFile Index entry of PackFiles of this version with the provided bitmask has the compression byte.
+    pub(crate) fn has_index_compression_byte(&self, bitmask: PFHFlags) -> bool {
+        *self == PFHVersion::PFH6 || (*self == PFHVersion::PFH5 && !bitmask.contains(PFHFlags::HAS_BIG_HEADER))
+    }
+
+    /// This function returns the size of the timestamp in the header of PackFiles of this version.
+    /// PFH2 and PFH3 PackFiles use 64 bits `FILETIME` timestamps, and PFH0 PackFiles don't have one.
+    pub(crate) fn get_header_timestamp_size(&self) -> u32 {
+        match *self {
+            PFHVersion::PFH6 | PFHVersion::PFH5 | PFHVersion::PFH4 => 4,
+            PFHVersion::PFH3 | PFHVersion::PFH2 => 8,
+            PFHVersion::PFH0 => 0,
+        }
+    }
+
+    /// This function returns the size of the timestamp of every PackedFile Index entry of PackFiles of this version with the provided bitmask.
+    /// PFH2 and PFH3 PackFiles use 64 bits `FILETIME` timestamps, and PFH0 PackFiles don't have them.
+    pub(crate) fn get_index_timestamp_size(&self, bitmask: PFHFlags) -> u32 {
+        if !bitmask.contains(PFHFlags::HAS_INDEX_WITH_TIMESTAMPS) {
+            0
+        } else {
+            match *self {
+                PFHVersion::PFH3 | PFHVersion::PFH2 => 8,
+                PFHVersion::PFH0 => 0,
+                _ => 4
+            }
+        }
+    }
+}
+
+impl PathPattern {
+
+    /// This function creates a `PathPattern` from a glob pattern. `\` in the pattern is treated as a separator.
+    pub fn glob(pattern: &str) -> Result<Self> {
+        let pattern = glob::Pattern::new(&parse::normalize_path(pattern)).map_err(|error| Error::InvalidPatternError(error.to_string()))?;
+        Ok(PathPattern { matcher: PathMatcher::Glob(pattern) })
+    }
+
+    /// This function creates a `PathPattern` from a regular expression.
+    pub fn regex(pattern: &str) -> Result<Self> {
+        let pattern = regex::Regex::new(pattern).map_err(|error| Error::InvalidPatternError(error.to_string()))?;
+        Ok(PathPattern { matcher: PathMatcher::Regex(pattern) })
+    }
+
+    /// This function returns if the provided path matches the pattern.
+    pub fn is_match(&self, path: &str) -> bool {
+        let path = parse::normalize_path(path);
+        match self.matcher {
+            PathMatcher::Glob(ref pattern) => pattern.matches_with(&path, glob::MatchOptions {
+                case_sensitive: true,
+                require_literal_separator: true,
+                require_literal_leading_dot: false
+            }),
+            PathMatcher::Regex(ref pattern) => pattern.is_match(&path)
+        }
+    }
+}
+
+impl PackHeader {
+
+    /// This function returns the size of the header, Subheader or extended header included.
+    pub fn get_header_size(&self) -> u32 {
+        self.version.get_header_size(self.bitmask)
+    }
+
+    /// This function returns the position of the PackedFile Index in the PackFile.
+    pub fn get_packed_file_index_position(&self) -> u64 {
+        self.get_header_size() as u64 + self.pack_file_index_size as u64
+    }
+
+    /// This function returns the position where the data of the first PackedFile starts.
+    pub fn get_content_position(&self) -> u64 {
+        let unpadded = self.get_packed_file_index_position() + self.packed_file_index_size as u64;
+        if self.version.has_padding(self.bitmask) {
+            (unpadded + 7) & !7
+        } else {
+            unpadded
+        }
+    }
+}
+
+impl Default for PFHSubheader {
+    fn default() -> Self {
+        PFHSubheader {
+            version: 1,
+            game_version: 0,
+            build_number: 0,
+            authoring_tool: String::new(),
+            extra_data: vec![0; 256]
         }
     }
 }
 
 impl PackFile {
 
+    /// This function returns the [`PackHeader`](struct.PackHeader.html) of the provided PackFile.
+    pub fn get_header(&self) -> &PackHeader {
+        &self.header
+    }
+
     /// This function returns the [`PFHVersion`](enum.PFHVersion.html) of the provided PackFile.
     pub fn get_version(&self) -> ::PFHVersion {
-        match parse::get_preamble(&self.view) {
-            PFH5_PREAMBLE => PFHVersion::PFH5,
-            PFH4_PREAMBLE => PFHVersion::PFH4,
-            _ => unreachable!()
-        }
+        self.header.version
     }
 
     /// This function returns the [`PFHFileType`](enum.PFHFileType.html) of the provided PackFile.
     pub fn get_file_type(&self) -> PFHFileType {
-        match parse::get_file_type(&self.view) {
-            FILE_TYPE_BOOT => PFHFileType::Boot,
-            FILE_TYPE_RELEASE => PFHFileType::Release,
-            FILE_TYPE_PATCH => PFHFileType::Patch,
-            FILE_TYPE_MOD => PFHFileType::Mod,
-            FILE_TYPE_MOVIE => PFHFileType::Movie,
-            value => PFHFileType::Other(value)
-        }
+        self.header.file_type
     }
 
     /// This function returns the [`PFHFlags`](enum.PFHFlags.html) of the provided PackFile.
     pub fn get_bitmask(&self) -> ::PFHFlags {
-        parse::get_bitmask(&self.view)
+        self.header.bitmask
     }
 
     /// This function returns the `Timestamp` stored in the header of the provided PackFile, if any.
     ///
-    /// Keep in mind this `Timestamp` is in `u32` format. If you want to actually check it, you have to convert it to something readable.
-    pub fn get_timestamp(&self) -> u32 {
-        parse::get_timestamp(&self.view)
+    /// Keep in mind this `Timestamp` is a 64 bits `FILETIME` in PFH2 and PFH3 PackFiles, and a `u32` in newer ones. PFH0 PackFiles don't have one, so you'll get 0.
+    /// If you want to actually check it, you have to convert it to something readable.
+    pub fn get_timestamp(&self) -> u64 {
+        self.header.timestamp
+    }
+
+    /// This function returns the [`PFHSubheader`](struct.PFHSubheader.html) of the provided PackFile, if it's a PFH6 PackFile.
+    pub fn get_subheader(&self) -> Option<PFHSubheader> {
+        self.header.subheader.clone()
+    }
+
+    /// This function returns the version of the game the provided PackFile was made for, if it's a PFH6 PackFile.
+    pub fn get_game_version(&self) -> Option<u32> {
+        self.get_subheader().map(|subheader| subheader.game_version)
+    }
+
+    /// This function returns the build number of the game the provided PackFile was made for, if it's a PFH6 PackFile.
+    pub fn get_build_number(&self) -> Option<u32> {
+        self.get_subheader().map(|subheader| subheader.build_number)
+    }
+
+    /// This function returns the [`PFHBigHeader`](struct.PFHBigHeader.html) of the provided PackFile, if it has `HAS_BIG_HEADER` in his bitmask.
+    pub fn get_big_header(&self) -> Option<PFHBigHeader> {
+        self.header.big_header.clone()
+    }
+
+    /// This function returns the signature at the end of the provided PackFile, if it has `HAS_BIG_HEADER` in his bitmask.
+    pub fn get_signature(&self) -> Option<Vec<u8>> {
+        self.get_big_header().map(|big_header| big_header.signature)
     }
 
     /// This function returns the `PackFile Index` some PackFiles have after their header.
     ///
     /// It's a `Vec<String>` with values like `test1.pack`. The game seems to force PackFiles in this Index (if it finds them) to be loaded before the current one.
     pub fn get_pack_file_index(&self) -> Vec<String> {
-        parse::get_pack_file_index(&self.view)
+        self.pack_file_index.clone()
+    }
+
+    /// This function checks that the bitmask of the provided PackFile only has flags his version supports, like
+    /// `PackBuilder::validate` does before building one. Parsing doesn't do it, so slightly broken PackFiles can still be read.
+    pub fn validate(&self) -> Result<()> {
+        self.header.version.check_bitmask(self.header.bitmask)
+    }
+
+    /// This function returns the PackedFile with the provided path, if the PackFile contains it. The data is not loaded.
+    ///
+    /// `/` and `\` are treated as the same separator, like the game does. The comparison is case-sensitive.
+    pub fn get(&self, path: &str) -> Option<PackedFile> {
+        self.get_packed_file(path, true)
+    }
+
+    /// This function returns if the PackFile contains a PackedFile with the provided path. The comparison is case-sensitive.
+    pub fn contains(&self, path: &str) -> bool {
+        self.paths.find(path, true).is_some()
+    }
+
+    /// This function returns the PackedFile with the provided path, if the PackFile contains it, ignoring the case of the path.
+    pub fn get_ignore_case(&self, path: &str) -> Option<PackedFile> {
+        self.get_packed_file(path, false)
+    }
+
+    /// This function returns if the PackFile contains a PackedFile with the provided path, ignoring the case of the path.
+    pub fn contains_ignore_case(&self, path: &str) -> bool {
+        self.paths.find(path, false).is_some()
+    }
+
+    /// This function returns an iterator over the PackedFiles of the provided PackFile. Unlike `into_iter`, it reports
+    /// the first malformed entry of the PackedFile Index as an `Error::IndexEntryError` instead of silently stopping.
+    pub fn entries<'a>(&'a self) -> impl Iterator<Item = Result<PackedFile>> + 'a {
+        parse::get_entries(self)
+    }
+
+    /// This function returns an iterator over the PackedFiles whose path matches the provided [`PathPattern`](struct.PathPattern.html).
+    /// Their data is not loaded.
+    pub fn find<'a>(&'a self, pattern: &'a PathPattern) -> impl Iterator<Item = PackedFile> + 'a {
+        self.index.iter()
+            .filter(move |entry| pattern.is_match(&entry.path))
+            .map(move |entry| entry.to_packed_file(&self.view, &self.header))
+    }
+
+    /// This function returns the root [`PackFolder`](struct.PackFolder.html) of the directory tree of the provided PackFile.
+    /// No data is loaded to build it.
+    pub fn get_tree(&self) -> PackFolder {
+        tree::build_tree(&self.index)
+    }
+
+    fn get_packed_file(&self, path: &str, case_sensitive: bool) -> Option<PackedFile> {
+        self.paths.find(path, case_sensitive).map(|position| self.index[position].to_packed_file(&self.view, &self.header))
     }
 }
 
@@ -212,14 +656,15 @@ impl PackedFile {
     /// This function creates a new PackedFile with the provided info.
     ///
     /// It requires:
-    /// - `timestamp`: a timestamp in `u32` format of the PackedFile, usually his `last modified` date. Optional.
+    /// - `timestamp`: a timestamp of the PackedFile, usually his `last modified` date. Optional.
     /// - `path`: a path of type `a/b/c.whatever`.
     /// - `data`: the data to be contained in the PackedFile. For an empty PackedFile, just pass an empty vector.
-    pub fn new(timestamp: Option<u32>, path: String, data: Vec<u8>) -> Self {
+    pub fn new(timestamp: Option<u64>, path: String, data: Vec<u8>) -> Self {
         PackedFile {
             data: Mutex::new(PackedFileData::DataBacked(Arc::new(data))),
             timestamp,
-            path
+            path,
+            is_compressed: false
         }
     }
 
@@ -228,13 +673,7 @@ impl PackedFile {
     pub fn load_data(&self) -> Result<()> {
         let packed_file_data = &mut *self.data.lock().unwrap();
         let data = if let PackedFileData::LazyLoading(lazy) = packed_file_data {
-            if lazy.is_encrypted {
-                let plaintext = crypto::decrypt_file(&lazy.file_view.read_raw(&lazy.range)?, (lazy.range.end - lazy.range.start) as usize, false);
-                assert!(plaintext.len() as u64 == lazy.range.end - lazy.range.start, format!("{} != {}", plaintext.len(), lazy.range.end - lazy.range.start));
-                Arc::new(plaintext)
-            } else {
-                Arc::new(lazy.file_view.read_raw(&lazy.range)?)
-            }
+            Arc::new(self.with_path(lazy.read())?)
         } else { return Ok(()) };
         *packed_file_data = PackedFileData::DataBacked(data);
         Ok(())
@@ -249,13 +688,7 @@ impl PackedFile {
                 if DEBUG {
                     println!("PackedFile get_data (0x{:x?}-0x{:x?})", lazy.range.start, lazy.range.end);
                 }
-                if lazy.is_encrypted {
-                    let plaintext = crypto::decrypt_file(&lazy.file_view.read_raw(&lazy.range)?.to_vec(), (lazy.range.end - lazy.range.start) as usize, false);
-                    assert!(plaintext.len() as u64 == lazy.range.end - lazy.range.start, format!("{} != {}", plaintext.len(), lazy.range.end - lazy.range.start));
-                    Arc::new(plaintext)
-                } else {
-                    Arc::new(lazy.file_view.read_raw(&lazy.range)?)
-                }
+                Arc::new(self.with_path(lazy.read())?)
             },
             PackedFileData::DataBacked(data) => {
                 return Ok(data.clone());
@@ -265,6 +698,39 @@ impl PackedFile {
         Ok(data)
     }
 
+    /// This function tries to return the data of the PackedFile like it's stored in a PackFile. That means if the PackedFile is
+    /// compressed you'll get the compressed data. If it's encrypted, it'll still be decrypted.
+    ///
+    /// If the data of a compressed PackedFile is already loaded in memory (or the PackedFile was not compressed in his PackFile), it has to be compressed again.
+    pub fn get_raw_data(&self) -> Result<Arc<Vec<u8>>> {
+        if let PackedFileData::LazyLoading(lazy) = &*self.data.lock().unwrap() {
+            if lazy.is_compressed == self.is_compressed {
+                return Ok(Arc::new(self.with_path(lazy.read_raw())?))
+            }
+        }
+        if self.is_compressed {
+            Ok(Arc::new(self.with_path(compression::compress_data(&self.get_data()?))?))
+        } else {
+            self.get_data()
+        }
+    }
+
+    /// This function returns where the data of the PackedFile is in his PackFile, if it's lazy-loaded and it's not loaded yet.
+    pub(crate) fn get_lazy_data(&self) -> Option<LazyLoadingPackedFile> {
+        match &*self.data.lock().unwrap() {
+            PackedFileData::LazyLoading(lazy) => Some(lazy.clone()),
+            PackedFileData::DataBacked(_) => None
+        }
+    }
+
+    /// This function adds the path of the PackedFile to the errors reading or writing his data.
+    fn with_path<T>(&self, result: Result<T>) -> Result<T> {
+        result.map_err(|error| Error::PackedFileError {
+            path: self.path.clone(),
+            source: Box::new(error)
+        })
+    }
+
     /// This function replaces whatever data the PackedFile has with the data provided to it.
     pub fn set_data(&mut self, data: Arc<Vec<u8>>) {
         let packed_file_data = &mut *self.data.lock().unwrap();
@@ -278,12 +744,14 @@ impl Clone for PackedFile {
             PackedFileData::DataBacked(ref data) => PackedFile {
                 data: Mutex::new(PackedFileData::DataBacked(data.clone())),
                 timestamp: self.timestamp,
-                path: self.path.clone()
+                path: self.path.clone(),
+                is_compressed: self.is_compressed
             },
             PackedFileData::LazyLoading(ref lazy) => PackedFile {
                 data: Mutex::new(PackedFileData::LazyLoading(lazy.clone())),
                 timestamp: self.timestamp,
-                path: self.path.clone()
+                path: self.path.clone(),
+                is_compressed: self.is_compressed
             }
         }
     }
@@ -291,22 +759,43 @@ impl Clone for PackedFile {
 
 impl fmt::Debug for PackedFile {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "PackedFile {{ timestamp: {:?}, path: {:?} }}", self.timestamp, &self.path)
+        write!(f, "PackedFile {{ timestamp: {:?}, path: {:?}, is_compressed: {:?} }}", self.timestamp, &self.path, self.is_compressed)
     }
 }
 
 /// This function tries to create a `PackFile` struct by parsing a file.
 pub fn parse_pack(input_file: File) -> Result<::PackFile> {
-    let pack_file = parse::parse_pack(input_file)?;
+    let pack_file = parse::parse_pack(PackSource::File(FileView::new(input_file)?))?;
     Ok(pack_file)
 }
 
-/// This function tries to create a `PackFile` in the filesystem from individual files.
-pub fn build_pack_from_filesystem(input_directory: &Path, output_file: &mut File, version: PFHVersion, bitmask: PFHFlags, file_type: ::PFHFileType, pfh_timestamp: u32, pack_files: &[String]) -> Result<()> {
-    build::build_pack_from_filesystem(input_directory, output_file, version, bitmask, file_type, pfh_timestamp, pack_files)
+/// This function tries to create a `PackFile` struct by parsing a PackFile already in memory.
+pub fn parse_pack_from_bytes(input: Vec<u8>) -> Result<::PackFile> {
+    parse::parse_pack(PackSource::Memory(Arc::new(input)))
+}
+
+/// This function tries to create a `PackFile` struct by parsing a PackFile from any `Read + Seek` source.
+///
+/// The source is kept open, so the data of the PackedFiles can still be loaded lazily.
+pub fn parse_pack_from_reader<R: Read + Seek + Send + 'static>(input: R) -> Result<::PackFile> {
+    parse::parse_pack(PackSource::from_reader(input)?)
 }
 
-/// This function tries to create a `PackFile` in the filesystem from PackedFiles.
-pub fn build_pack_from_memory<P: Borrow<PackedFile>>(input: &mut Vec<P>, output_file: &mut File, version: PFHVersion, bitmask: PFHFlags, file_type: ::PFHFileType, pfh_timestamp: u32, pack_files: &[String]) -> Result<()> {
-    build::build_pack_from_memory(pack_files, input, output_file, version, bitmask, file_type, pfh_timestamp)
+/// This function tries to create a `PackFile` from individual files, and write it into `output_file`.
+///
+/// The output can be anything implementing `Write`, like a `File`, a `Vec<u8>` or `stdout`. It's written sequentially, without seeking.
+/// PackedFiles are written uncompressed. Use a [`PackBuilder`](struct.PackBuilder.html) to set a subheader, a big header or a compression policy.
+#[deprecated(note = "use a `PackBuilder` and its `add_directory` method instead")]
+pub fn build_pack_from_filesystem<W: Write>(input_directory: &Path, output_file: &mut W, version: PFHVersion, bitmask: PFHFlags, file_type: ::PFHFileType, pfh_timestamp: u32, pack_files: &[String]) -> Result<()> {
+    build::build_pack_from_filesystem(input_directory, output_file, version, bitmask, file_type, u64::from(pfh_timestamp), pack_files)
+}
+
+/// This function tries to create a `PackFile` from PackedFiles, and write it into `output_file`.
+///
+/// The output can be anything implementing `Write`, like a `File`, a `Vec<u8>` or `stdout`. It's written sequentially, without seeking.
+/// PackedFiles keep the compression they had in their PackFiles. They're written sorted by path, but `input` is left untouched.
+/// Use a [`PackBuilder`](struct.PackBuilder.html) to set a subheader, a big header or a compression policy.
+#[deprecated(note = "use a `PackBuilder` instead")]
+pub fn build_pack_from_memory<W: Write, P: Borrow<PackedFile>>(input: &[P], output_file: &mut W, version: PFHVersion, bitmask: PFHFlags, file_type: ::PFHFileType, pfh_timestamp: u32, pack_files: &[String]) -> Result<()> {
+    build::build_pack_from_memory(pack_files, input, output_file, version, bitmask, file_type, u64::from(pfh_timestamp))
 }
@@ -1,31 +1,79 @@
+use std::cmp;
+use std::collections::HashMap;
 use std::fmt;
-use std::fs::File;
 use std::ops::Range;
+use std::result;
+use std::slice;
+use std::sync::Arc;
 use std::sync::Mutex;
 
 use byteorder::LittleEndian;
 use byteorder::ByteOrder;
-use cached_file_view::FileView;
+use source::PackSource;
 
 use error::{Error, Result};
 
+/// Maximum size of the signature at the end of PackFiles with `HAS_BIG_HEADER`.
+const MAX_SIGNATURE_SIZE: u64 = 0x100;
+
 pub struct PackIndexIterator<'a> {
-    view: &'a FileView,
-    next_item: u32,
-    index_position: u32,
-    content_position: u32
+    view: &'a PackSource,
+    header: &'a ::PackHeader,
+    entries: slice::Iter<'a, PackIndexEntry>
+}
+
+pub struct PackEntriesIterator<'a> {
+    view: &'a PackSource,
+    header: &'a ::PackHeader,
+    entries: slice::Iter<'a, PackIndexEntry>,
+    index_error: Option<IndexError>
+}
+
+/// The position of the first malformed entry of the PackedFile Index, his offset from the start of the Index, and what's wrong with it.
+#[derive(Clone, Copy, Debug)]
+pub struct IndexError {
+    pub entry: u32,
+    pub offset: u64,
+    pub reason: &'static str
+}
+
+/// An entry of the PackedFile Index, decoded when the PackFile is parsed.
+#[derive(Clone, Debug)]
+pub struct PackIndexEntry {
+    pub path: String,
+    pub timestamp: Option<u64>,
+    pub is_compressed: bool,
+    pub range: Range<u64>
+}
+
+/// Lookup tables from the paths of the PackedFiles to their position in the PackedFile Index.
+pub struct PathIndex {
+    exact: HashMap<String, usize>,
+    case_insensitive: HashMap<String, usize>
+}
+
+struct IndexDecoder<'a> {
+    header: &'a ::PackHeader,
+    raw_index: &'a [u8],
+    file_length: u64,
+    remaining_items: u32,
+    index_position: usize,
+    content_position: u64
 }
 
 #[derive(Clone)]
 pub struct LazyLoadingPackedFile {
-    pub file_view: FileView,
+    pub file_view: PackSource,
     pub range: Range<u64>,
-    pub is_encrypted: bool
+    pub is_encrypted: bool,
+    pub is_compressed: bool,
+    pub has_padding: bool
 }
 
 impl fmt::Display for ::PackFile {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "PackFile (encrypted index: {}, encrypted content: {}, padding: {}, timestamped files: {})", has_encrypted_index(&self.view), has_encrypted_content(&self.view), has_padding(&self.view), has_index_with_timestamps(&self.view))
+        let header = &self.header;
+        write!(f, "PackFile (encrypted index: {}, encrypted content: {}, padding: {}, timestamped files: {})", header.bitmask.contains(::PFHFlags::HAS_ENCRYPTED_INDEX), header.bitmask.contains(::PFHFlags::HAS_ENCRYPTED_CONTENT), header.version.has_padding(header.bitmask), header.version.get_index_timestamp_size(header.bitmask) > 0)
     }
 }
 
@@ -39,110 +87,116 @@ impl<'a> IntoIterator for &'a ::PackFile {
     type Item = ::PackedFile;
     type IntoIter = PackIndexIterator<'a>;
     fn into_iter(self) -> Self::IntoIter {
-        let payload_position = if has_padding(&self.view) {
-            let unpadded = get_static_header_size(&self.view) + get_pack_file_index_size(&self.view) + get_packed_file_index_size(&self.view);
-            let remainder = unpadded % 8;
-            if remainder > 0 {
-                unpadded + 8 - remainder
-            } else {
-                unpadded
-            }
-        } else {
-            get_static_header_size(&self.view) + get_pack_file_index_size(&self.view) + get_packed_file_index_size(&self.view)
-        };
         PackIndexIterator {
             view: &self.view,
-            next_item: get_packed_file_index_length(&self.view),
-            index_position: (get_static_header_size(&self.view) + get_pack_file_index_size(&self.view)),
-            content_position: payload_position
+            header: &self.header,
+            entries: self.index.iter()
         }
     }
 }
 
-pub fn get_preamble(view: &FileView) -> u32 {
-    LittleEndian::read_u32(&view.read(0x00..0x04).unwrap().to_vec())
-}
-
-pub fn get_file_type(view: &FileView) -> u32 {
-    LittleEndian::read_u32(&view.read(0x04..0x08).unwrap().to_vec()) & 0xf
-}
-
-pub fn get_bitmask(view: &FileView) -> ::PFHFlags {
-    ::PFHFlags::from_bits_truncate(LittleEndian::read_u32(&view.read(0x04..0x08).unwrap().to_vec()) & !0xf)
-}
-
-pub fn get_timestamp(view: &FileView) -> u32 {
-    LittleEndian::read_u32(&view.read(0x18..0x1C).unwrap().to_vec())
-}
-
-/// Get the amount of items in the PackFile Index.
-fn get_pack_file_index_length(view: &FileView) -> u32 {
-    LittleEndian::read_u32(&view.read(0x08..0x0C).unwrap().to_vec())
-}
-
-/// Get the size in bytes of the PackFile Index.
-fn get_pack_file_index_size(view: &FileView) -> u32 {
-    LittleEndian::read_u32(&view.read(0x0C..0x10).unwrap().to_vec())
-}
-
-/// Get the amount of items in the PackedFile Index.
-fn get_packed_file_index_length(view: &FileView) -> u32 {
-    LittleEndian::read_u32(&view.read(0x10..0x14).unwrap().to_vec())
-}
-
-/// Get the size in bytes of the PackedFile Index.
-fn get_packed_file_index_size(view: &FileView) -> u32 {
-    LittleEndian::read_u32(&view.read(0x14..0x18).unwrap().to_vec())
-}
-
-fn _get_signature_offset(view: &FileView) -> u32 {
-    LittleEndian::read_u32(&view.read(0x28..0x2C).unwrap().to_vec())
-}
-
-fn get_static_header_size(raw_data: &FileView) -> u32 {
-    if get_preamble(&raw_data) == ::PFH4_PREAMBLE {
-        0x1C
-    } else if get_preamble(&raw_data) == ::PFH5_PREAMBLE {
-        if has_big_header(&raw_data) {
-            0x30
-        } else {
-            0x1C
-        }
-    } else {
-        panic!("Invalid preamble! ###{:?}", get_preamble(&raw_data))
+pub fn get_entries<'a>(pack_file: &'a ::PackFile) -> PackEntriesIterator<'a> {
+    PackEntriesIterator {
+        view: &pack_file.view,
+        header: &pack_file.header,
+        entries: pack_file.index.iter(),
+        index_error: pack_file.index_error
     }
 }
 
-fn has_big_header(view: &FileView) -> bool {
-    get_bitmask(&view).contains(::PFHFlags::HAS_BIG_HEADER)
-}
-
-fn has_encrypted_index(view: &FileView) -> bool {
-    get_bitmask(&view).contains(::PFHFlags::HAS_ENCRYPTED_INDEX)
-}
+/// Decode the header of the PackFile, Subheader and extended header included.
+pub fn parse_header(view: &PackSource) -> Result<::PackHeader> {
+    if view.get_length() < 0x18 {
+        return Err(Error::InvalidFileError(format!("the file is too short to be a PackFile ({} bytes)", view.get_length())))
+    }
+    let raw_header = view.read(&(0x00..0x18))?;
+    let preamble = LittleEndian::read_u32(&raw_header[0x00..0x04]);
+    let version = ::PFHVersion::from_preamble(preamble).ok_or_else(|| Error::InvalidHeaderError(format!("unknown preamble 0x{:08X}", preamble)))?;
+    let raw_flags = LittleEndian::read_u32(&raw_header[0x04..0x08]);
+    if raw_flags & 0xf > 4 {
+        return Err(Error::InvalidHeaderError(format!("unknown file type {}", raw_flags & 0xf)))
+    }
+    let file_type = ::PFHFileType::from_value(raw_flags & 0xf);
+    if ::PFHFlags::from_bits(raw_flags & !0xf).is_none() {
+        eprintln!("Warning: Bitmask has unknown bits set")
+    }
+    let bitmask = ::PFHFlags::from_bits_truncate(raw_flags & !0xf);
 
-fn has_index_with_timestamps(view: &FileView) -> bool {
-    get_bitmask(&view).contains(::PFHFlags::HAS_INDEX_WITH_TIMESTAMPS)
+    let header_size = version.get_header_size(bitmask) as u64;
+    if view.get_length() < header_size {
+        return Err(Error::InvalidFileError(format!("the file is shorter than his 0x{:x} bytes header", header_size)))
+    }
+    let raw_header = view.read(&(0x00..header_size))?;
+    let timestamp = match version {
+        ::PFHVersion::PFH0 => 0,
+        ::PFHVersion::PFH3 | ::PFHVersion::PFH2 => LittleEndian::read_u64(&raw_header[0x18..0x20]),
+        _ => LittleEndian::read_u32(&raw_header[0x18..0x1C]) as u64
+    };
+    let content_position = header_size + LittleEndian::read_u32(&raw_header[0x0C..0x10]) as u64 + LittleEndian::read_u32(&raw_header[0x14..0x18]) as u64;
+
+    Ok(::PackHeader {
+        version,
+        file_type,
+        bitmask,
+        pack_file_index_length: LittleEndian::read_u32(&raw_header[0x08..0x0C]),
+        pack_file_index_size: LittleEndian::read_u32(&raw_header[0x0C..0x10]),
+        packed_file_index_length: LittleEndian::read_u32(&raw_header[0x10..0x14]),
+        packed_file_index_size: LittleEndian::read_u32(&raw_header[0x14..0x18]),
+        timestamp,
+        subheader: if version == ::PFHVersion::PFH6 { Some(parse_subheader(&raw_header[0x1C..0x134])?) } else { None },
+        big_header: if version.has_big_header(bitmask) { Some(parse_big_header(view, &raw_header[0x1C..0x30], content_position)?) } else { None }
+    })
 }
 
-fn has_encrypted_content(view: &FileView) -> bool {
-    get_bitmask(&view).contains(::PFHFlags::HAS_ENCRYPTED_CONTENT)
+/// Decode the Subheader of PFH6 PackFiles. It starts with a 0x12345678 mark, followed by the Subheader version, the game version,
+/// the build number, 8 bytes for the name of the authoring tool and 256 bytes of unknown data.
+fn parse_subheader(raw_subheader: &[u8]) -> Result<::PFHSubheader> {
+    let mark = LittleEndian::read_u32(&raw_subheader[0x00..0x04]);
+    if mark != ::SUBHEADER_MARK {
+        return Err(Error::InvalidHeaderError(format!("the Subheader starts with 0x{:08x} instead of 0x{:08x}", mark, ::SUBHEADER_MARK)))
+    }
+    let raw_authoring_tool = raw_subheader[0x10..0x18].iter().take_while(|c| **c != 0).cloned().collect();
+    let authoring_tool = String::from_utf8(raw_authoring_tool).map_err(|_| Error::InvalidHeaderError("the authoring tool is not valid UTF-8".to_owned()))?;
+    Ok(::PFHSubheader {
+        version: LittleEndian::read_u32(&raw_subheader[0x04..0x08]),
+        game_version: LittleEndian::read_u32(&raw_subheader[0x08..0x0C]),
+        build_number: LittleEndian::read_u32(&raw_subheader[0x0C..0x10]),
+        authoring_tool,
+        extra_data: raw_subheader[0x18..].to_vec()
+    })
 }
 
-fn has_padding(raw_data: &FileView) -> bool {
-    get_preamble(&raw_data) == ::PFH5_PREAMBLE && has_encrypted_content(&raw_data)
+/// Decode the extended header of PackFiles with `HAS_BIG_HEADER`, and the signature at the end of the file.
+///
+/// An offset outside of the content, like the zero older tools write, means there is no signature. We read `MAX_SIGNATURE_SIZE` bytes
+/// of it at most, so a corrupt offset can't make us load the whole PackFile.
+fn parse_big_header(view: &PackSource, raw_big_header: &[u8], content_position: u64) -> Result<::PFHBigHeader> {
+    let signature_offset = LittleEndian::read_u32(&raw_big_header[0x0C..0x10]);
+    let signature = if (signature_offset as u64) >= content_position && (signature_offset as u64) < view.get_length() {
+        view.read(&(signature_offset as u64..cmp::min(signature_offset as u64 + MAX_SIGNATURE_SIZE, view.get_length())))?
+    } else {
+        vec!()
+    };
+    Ok(::PFHBigHeader {
+        unknown_1: LittleEndian::read_u32(&raw_big_header[0x00..0x04]),
+        unknown_2: LittleEndian::read_u32(&raw_big_header[0x04..0x08]),
+        unknown_3: LittleEndian::read_u32(&raw_big_header[0x08..0x0C]),
+        signature_offset,
+        unknown_4: LittleEndian::read_u32(&raw_big_header[0x10..0x14]),
+        signature
+    })
 }
 
-pub fn get_pack_file_index(view: &FileView) -> Vec<String> {
-    let raw_index = view.read(get_static_header_size(view) as u64..(get_static_header_size(view) + get_pack_file_index_size(view)) as u64).unwrap().to_vec();
+/// Decode the PackFile Index, a list of null-terminated PackFile names.
+fn decode_pack_file_index(raw_index: &[u8], header: &::PackHeader) -> Result<Vec<String>> {
     let mut pack_file_index = vec![];
     let mut pos: usize = 0;
-    for _ in 0..get_pack_file_index_length(view) {
+    for _ in 0..header.pack_file_index_length {
         let mut pack_file_name = String::new();
 
         // For each byte...
         loop {
-            let character = raw_index[pos];
+            let character = *raw_index.get(pos).ok_or_else(|| Error::InvalidFileError(format!("the PackFile Index ends in the middle of the name at offset 0x{:x}", pos)))?;
             if character == 0 {
                 pack_file_index.push(pack_file_name);
                 pos += 1;
@@ -153,138 +207,229 @@ pub fn get_pack_file_index(view: &FileView) -> Vec<String> {
             }
         }
     }
-    pack_file_index
+    Ok(pack_file_index)
 }
 
 
-impl<'a> PackIndexIterator<'a> {
-    fn read_index_u32(&self) -> Result<u32> {
-        Ok(LittleEndian::read_u32(&self.view.read(self.index_position as u64..self.index_position as u64 + 4 as u64)?.to_vec()))
+impl<'a> IndexDecoder<'a> {
+    fn read_bytes(&mut self, size: usize) -> result::Result<&'a [u8], &'static str> {
+        let bytes = self.raw_index.get(self.index_position..self.index_position + size).ok_or("the entry is truncated")?;
+        self.index_position += size;
+        Ok(bytes)
     }
 
-    fn get_next(&mut self) -> Result<::PackedFile> {
-        if self.next_item >= 1 {
-            self.next_item -= 1;
+    /// Decode the next entry of the Index. On failure, it returns what's wrong with the entry.
+    fn decode_next(&mut self) -> result::Result<PackIndexEntry, &'static str> {
+        if self.remaining_items == 0 {
+            return Err("there are no more entries")
+        }
+        self.remaining_items -= 1;
 
-            // read 4 bytes item length
-            let mut item_length = self.read_index_u32()?;
-            item_length = if has_encrypted_index(&self.view) {
-                ::crypto::decrypt_index_item_file_length(self.next_item, item_length)
-            } else {
-                item_length
-            };
-            self.index_position = self.index_position.checked_add(4).ok_or(Error::IndexIteratorError)?;
-
-            // read 4 bytes whatever, if present
-            let timestamp = if has_index_with_timestamps(&self.view) {
-                let d = self.read_index_u32()?;
-                self.index_position = self.index_position.checked_add(4).ok_or(Error::IndexIteratorError)?;
-                Some(d)
-            } else {
-                None
-            };
+        // read 4 bytes item length
+        let mut item_length = LittleEndian::read_u32(self.read_bytes(4)?);
+        if self.header.bitmask.contains(::PFHFlags::HAS_ENCRYPTED_INDEX) {
+            item_length = ::crypto::decrypt_index_item_file_length(self.remaining_items, item_length);
+        }
 
-            if get_preamble(&self.view) == ::PFH5_PREAMBLE && !has_big_header(&self.view) {
-                self.index_position = self.index_position.checked_add(1).ok_or(Error::IndexIteratorError)?;
-            }
+        // read 4 bytes timestamp (8 bytes in PFH2 and PFH3), if present
+        let timestamp = match self.header.version.get_index_timestamp_size(self.header.bitmask) {
+            8 => Some(LittleEndian::read_u64(self.read_bytes(8)?)),
+            4 => Some(LittleEndian::read_u32(self.read_bytes(4)?) as u64),
+            _ => None
+        };
 
-            let remaining_index_size = get_packed_file_index_size(&self.view) - (self.index_position - get_static_header_size(&self.view) - get_pack_file_index_size(&self.view));
-            let (file_path, len) = if has_encrypted_index(&self.view) {
-                ::crypto::decrypt_index_item_filename(&self.view.read(self.index_position as u64..(self.index_position + remaining_index_size) as u64)?.to_vec(), item_length as u8)
-            } else {
-                let mut  buf = vec!();
-                let mut i = 0;
-                loop {
-                    let c = self.view.read((self.index_position + i) as u64..(self.index_position + i + 1) as u64)?.to_vec()[0];
-                    i += 1;
-                    if c == 0 {
-                        break;
-                    }
-                    buf.push(c);
-                    if i >= remaining_index_size {
-                        return Err(Error::IndexIteratorError);
-                    }
-                }
-                (buf, i)
-            };
-            self.index_position += len;
-
-            let padded_item_length = if has_encrypted_content(&self.view) {
-                let remainder = item_length % 8;
-                if remainder > 0 {
-                    item_length.checked_add(8-remainder).ok_or(Error::IndexIteratorError)?
-                } else {
-                    item_length
-                }
-            } else {
-                item_length
-            };
+        // read 1 byte compression flag, if present
+        let is_compressed = if self.header.version.has_index_compression_byte(self.header.bitmask) {
+            self.read_bytes(1)?[0] != 0
+        } else {
+            false
+        };
 
-            let start = self.content_position as u64;
-            let end = (self.content_position + item_length) as u64;
+        let raw_path = &self.raw_index[self.index_position..];
+        let (file_path, len) = if self.header.bitmask.contains(::PFHFlags::HAS_ENCRYPTED_INDEX) {
+            ::crypto::decrypt_index_item_filename(raw_path, item_length as u8).ok_or("the path has no null terminator")?
+        } else {
+            let len = raw_path.iter().position(|c| *c == 0).ok_or("the path has no null terminator")?;
+            (raw_path[..len].to_vec(), len as u32 + 1)
+        };
+        self.index_position += len as usize;
 
-            if has_padding(&self.view) {
-                self.content_position = self.content_position.checked_add(padded_item_length).ok_or(Error::IndexIteratorError)?;
-            } else {
-                self.content_position = self.content_position.checked_add(item_length).ok_or(Error::IndexIteratorError)?;
+        let start = self.content_position;
+        let end = start + item_length as u64;
+        if end > self.file_length {
+            return Err("the data ends past the end of the file")
+        }
+        self.content_position = if self.header.version.has_padding(self.header.bitmask) {
+            start + ((item_length as u64 + 7) & !7)
+        } else {
+            end
+        };
+
+        Ok(PackIndexEntry {
+            path: String::from_utf8(file_path).map_err(|_| "the path is not valid UTF-8")?,
+            timestamp,
+            is_compressed,
+            range: start..end
+        })
+    }
+}
+
+/// Decode the whole PackedFile Index in one pass. Decoding stops at the first malformed entry.
+fn decode_index(raw_index: &[u8], header: &::PackHeader, file_length: u64) -> (Vec<PackIndexEntry>, Option<IndexError>) {
+    let mut decoder = IndexDecoder {
+        header,
+        raw_index,
+        file_length,
+        remaining_items: header.packed_file_index_length,
+        index_position: 0,
+        content_position: header.get_content_position()
+    };
+    // Every entry takes at least 5 bytes, so a bogus length in the header can't make us allocate more than the Index needs.
+    let mut entries = Vec::with_capacity(cmp::min(header.packed_file_index_length as usize, raw_index.len() / 5));
+    while decoder.remaining_items > 0 {
+        let offset = decoder.index_position as u64;
+        match decoder.decode_next() {
+            Ok(entry) => entries.push(entry),
+            Err(reason) => {
+                let index_error = IndexError {
+                    entry: entries.len() as u32,
+                    offset,
+                    reason
+                };
+                return (entries, Some(index_error))
             }
+        }
+    }
+    (entries, None)
+}
 
-            Ok(::PackedFile {
-                timestamp,
-                path: String::from_utf8(file_path).map_err(|_| Error::IndexIteratorError)?,
-                data: Mutex::new(::PackedFileData::LazyLoading(LazyLoadingPackedFile {
-                        file_view: (*self.view).clone(),
-                        is_encrypted: has_encrypted_content(&self.view),
-                        range: start..end
-                    })
-                )
-            })
-        } else {
-            Err(Error::IndexIteratorError)
+impl PackIndexEntry {
+    pub fn to_packed_file(&self, view: &PackSource, header: &::PackHeader) -> ::PackedFile {
+        ::PackedFile {
+            timestamp: self.timestamp,
+            is_compressed: self.is_compressed,
+            path: self.path.clone(),
+            data: Mutex::new(::PackedFileData::LazyLoading(LazyLoadingPackedFile {
+                    file_view: view.clone(),
+                    is_encrypted: header.bitmask.contains(::PFHFlags::HAS_ENCRYPTED_CONTENT),
+                    is_compressed: self.is_compressed,
+                    has_padding: header.version.has_padding(header.bitmask),
+                    range: self.range.clone()
+                })
+            )
         }
     }
 }
 
-impl<'a> Iterator for PackIndexIterator<'a> {
-    type Item = ::PackedFile;
-    fn next(&mut self) -> Option<::PackedFile> {
-        match self.get_next() {
-            Ok(item) => Some(item),
-            Err(_) => None
+/// Normalize a path the way the game does, treating `/` and `\` as the same separator.
+pub fn normalize_path(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+impl PathIndex {
+    pub fn new(entries: &[PackIndexEntry]) -> Self {
+        let mut exact = HashMap::with_capacity(entries.len());
+        let mut case_insensitive = HashMap::with_capacity(entries.len());
+        for (position, entry) in entries.iter().enumerate() {
+            let path = normalize_path(&entry.path);
+            case_insensitive.entry(path.to_lowercase()).or_insert(position);
+            exact.entry(path).or_insert(position);
+        }
+        PathIndex {
+            exact,
+            case_insensitive
+        }
+    }
+
+    /// Get the position in the PackedFile Index of the first PackedFile with the provided path.
+    pub fn find(&self, path: &str, case_sensitive: bool) -> Option<usize> {
+        let path = normalize_path(path);
+        if case_sensitive {
+            self.exact.get(&path).cloned()
+        } else {
+            self.case_insensitive.get(&path.to_lowercase()).cloned()
         }
     }
 }
 
-pub fn parse_pack(input_file: File) -> Result<::PackFile> {
-    let file_view = FileView::new(input_file)?;
+impl LazyLoadingPackedFile {
 
-    if file_view.length < 4 || file_view.length < get_static_header_size(&file_view) as u64 {
-        return Err(Error::InvalidFileError)
+    /// Range of the data like it's stored in the PackFile, encrypted and with his padding, if all of it is inside the PackFile.
+    pub fn get_stored_range(&self) -> Option<Range<u64>> {
+        let end = if self.has_padding {
+            self.range.start + ((self.range.end - self.range.start + 7) & !7)
+        } else {
+            self.range.end
+        };
+        if end <= self.file_view.get_length() { Some(self.range.start..end) } else { None }
     }
 
-    if file_view.length < (get_static_header_size(&file_view) + get_pack_file_index_size(&file_view)) as u64 {
-        return Err(Error::InvalidFileError)
+    /// Read the data like it's stored in the PackFile, decrypting it if needed.
+    pub fn read_raw(&self) -> Result<Vec<u8>> {
+        if self.is_encrypted {
+            let plaintext = ::crypto::decrypt_file(&self.file_view.read(&self.range)?, (self.range.end - self.range.start) as usize, false);
+            if plaintext.len() as u64 != self.range.end - self.range.start {
+                return Err(Error::InvalidFileError(format!("decrypted {} bytes instead of {}", plaintext.len(), self.range.end - self.range.start)))
+            }
+            Ok(plaintext)
+        } else {
+            Ok(self.file_view.read(&self.range)?)
+        }
     }
 
-    if get_preamble(&file_view) == ::PFH3_PREAMBLE || get_preamble(&file_view) == ::PFH2_PREAMBLE || get_preamble(&file_view) == ::PFH0_PREAMBLE {
-        return Err(Error::UnsupportedPackFile)
+    /// Read the data, decrypting and decompressing it if needed.
+    pub fn read(&self) -> Result<Vec<u8>> {
+        if self.is_compressed {
+            ::compression::decompress_data(&self.read_raw()?)
+        } else {
+            self.read_raw()
+        }
+    }
+}
+
+impl<'a> Iterator for PackIndexIterator<'a> {
+    type Item = ::PackedFile;
+    fn next(&mut self) -> Option<::PackedFile> {
+        self.entries.next().map(|entry| entry.to_packed_file(self.view, self.header))
     }
 
-    if get_preamble(&file_view) != ::PFH5_PREAMBLE && get_preamble(&file_view) != ::PFH4_PREAMBLE {
-        return Err(Error::InvalidHeaderError)
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
     }
+}
 
-    if get_file_type(&file_view) > 4 {
-        return Err(Error::InvalidHeaderError)
+impl<'a> Iterator for PackEntriesIterator<'a> {
+    type Item = Result<::PackedFile>;
+    fn next(&mut self) -> Option<Result<::PackedFile>> {
+        match self.entries.next() {
+            Some(entry) => Some(Ok(entry.to_packed_file(self.view, self.header))),
+            None => self.index_error.take().map(|index_error| Err(Error::IndexEntryError {
+                entry: index_error.entry,
+                offset: index_error.offset,
+                reason: index_error.reason
+            }))
+        }
     }
+}
 
-    if !::PFHFlags::from_bits(LittleEndian::read_u32(&file_view.read(0x04..0x08)?.to_vec()) & !0xf).is_some() {
-        eprintln!("Warning: Bitmask has unknown bits set")
+pub fn parse_pack(file_view: PackSource) -> Result<::PackFile> {
+    let header = parse_header(&file_view)?;
+
+    let index_end = header.get_packed_file_index_position() + header.packed_file_index_size as u64;
+    if file_view.get_length() < index_end {
+        return Err(Error::InvalidFileError(format!("the indexes end at 0x{:x}, past the end of the file", index_end)))
     }
 
-    let begin = file_view.read(0..(get_static_header_size(&file_view) as u64 + get_pack_file_index_size(&file_view) as u64 + get_packed_file_index_size(&file_view) as u64))?;
+    let begin = file_view.read(&(0..index_end))?;
+    let pack_file_index = decode_pack_file_index(&begin[header.get_header_size() as usize..header.get_packed_file_index_position() as usize], &header)?;
+    let (index, index_error) = decode_index(&begin[header.get_packed_file_index_position() as usize..], &header, file_view.get_length());
+    let paths = PathIndex::new(&index);
     Ok(::PackFile {
         view: file_view,
-        begin: begin
+        header,
+        pack_file_index,
+        index: Arc::new(index),
+        index_error,
+        paths: Arc::new(paths)
     })
 }
@@ -0,0 +1,58 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use cached_file_view::FileView;
+
+use error::{Error, Result};
+
+/// Anything we can read a PackFile from.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// This enum represents where the data of a PackFile comes from. PackedFiles keep a clone of it to load their data lazily.
+///
+/// - `File`: a PackFile in the filesystem.
+/// - `Memory`: a PackFile already in memory.
+/// - `Reader`: a PackFile read through any `Read + Seek` source, and his length.
+#[derive(Clone)]
+pub enum PackSource {
+    File(FileView),
+    Memory(Arc<Vec<u8>>),
+    Reader(Arc<Mutex<Box<dyn ReadSeek + Send>>>, u64)
+}
+
+impl PackSource {
+    pub fn from_reader<R: Read + Seek + Send + 'static>(mut reader: R) -> Result<Self> {
+        let length = reader.seek(SeekFrom::End(0))?;
+        Ok(PackSource::Reader(Arc::new(Mutex::new(Box::new(reader))), length))
+    }
+
+    /// Get the length in bytes of the PackFile.
+    pub fn get_length(&self) -> u64 {
+        match *self {
+            PackSource::File(ref view) => view.length,
+            PackSource::Memory(ref data) => data.len() as u64,
+            PackSource::Reader(_, length) => length
+        }
+    }
+
+    /// Read the provided range of the PackFile.
+    pub fn read(&self, range: &Range<u64>) -> Result<Vec<u8>> {
+        if range.start > range.end || range.end > self.get_length() {
+            return Err(Error::InvalidFileError(format!("0x{:x}..0x{:x} is out of the bounds of the file", range.start, range.end)))
+        }
+        match *self {
+            PackSource::File(ref view) => Ok(view.read_raw(range)?),
+            PackSource::Memory(ref data) => Ok(data[range.start as usize..range.end as usize].to_vec()),
+            PackSource::Reader(ref reader, _) => {
+                let reader = &mut *reader.lock().map_err(|_| Error::InvalidFileError("a previous read panicked".to_owned()))?;
+                reader.seek(SeekFrom::Start(range.start))?;
+                let mut buffer = vec![0; (range.end - range.start) as usize];
+                reader.read_exact(&mut buffer)?;
+                Ok(buffer)
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+use parse::normalize_path;
+use parse::PackIndexEntry;
+
+impl ::PackFolder {
+    fn new(name: &str) -> Self {
+        ::PackFolder {
+            name: name.to_owned(),
+            file_count: 0,
+            size: 0,
+            folders: BTreeMap::new(),
+            files: BTreeMap::new()
+        }
+    }
+
+    /// This function returns the subfolder at the provided path, relative to this folder. An empty path returns this folder.
+    ///
+    /// `/` and `\` are treated as the same separator. The comparison is case-sensitive.
+    pub fn get_folder(&self, path: &str) -> Option<&::PackFolder> {
+        let path = normalize_path(path);
+        let mut folder = self;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            folder = folder.folders.get(name)?;
+        }
+        Some(folder)
+    }
+
+    fn insert(&mut self, entry: &PackIndexEntry) {
+        let path = normalize_path(&entry.path);
+        let mut names: Vec<&str> = path.split('/').filter(|name| !name.is_empty()).collect();
+        let file_name = match names.pop() {
+            Some(file_name) => file_name,
+            None => return
+        };
+        let size = entry.range.end - entry.range.start;
+
+        let mut folder = self;
+        for name in names {
+            folder.file_count += 1;
+            folder.size += size;
+            folder = folder.folders.entry(name.to_owned()).or_insert_with(|| ::PackFolder::new(name));
+        }
+        folder.file_count += 1;
+        folder.size += size;
+        folder.files.insert(file_name.to_owned(), ::PackFolderFile {
+            path: entry.path.clone(),
+            size,
+            is_compressed: entry.is_compressed
+        });
+    }
+}
+
+/// Build the directory tree of a PackFile from his PackedFile Index, without loading any data.
+pub fn build_tree(entries: &[PackIndexEntry]) -> ::PackFolder {
+    let mut root = ::PackFolder::new("");
+    for entry in entries {
+        root.insert(entry);
+    }
+    root
+}
@@ -1,15 +1,22 @@
 extern crate tw_pack_lib;
 
+use std::fs;
 use std::fs::File;
+use std::io::Cursor;
 use std::path::Path;
+use std::thread;
 
 use tw_pack_lib::PFHVersion;
 use tw_pack_lib::PFHFlags;
 use tw_pack_lib::PFHFileType;
+use tw_pack_lib::PFHSubheader;
+use tw_pack_lib::PFHCompressionPolicy;
+use tw_pack_lib::error::Error;
 
 #[test]
+#[allow(deprecated)]
 fn test_build_pfh5_pack() {
-    tw_pack_lib::build_pack_from_filesystem(&Path::new(&"tests/build/loadingscreen"),
+    tw_pack_lib::build_pack_from_filesystem(Path::new("tests/build/loadingscreen"),
                                              &mut File::create(Path::new("tests/build/loadingscreen_test.pack")).unwrap(),
                                              PFHVersion::PFH5,
                                              PFHFlags::HAS_BIG_HEADER,
@@ -17,3 +24,413 @@ fn test_build_pfh5_pack() {
                                              42,
                                              &["test1.pack".to_owned(), "test2.pack".to_owned()]).unwrap()
 }
+
+#[test]
+fn test_pack_builder_from_directory() {
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Mod);
+    builder.set_bitmask(PFHFlags::HAS_BIG_HEADER)
+        .set_timestamp(42)
+        .add_pack_file("test1.pack")
+        .add_pack_file("test2.pack")
+        .add_directory(Path::new("tests/build/loadingscreen")).unwrap();
+    let mut output = vec!();
+    builder.finish(&mut output).unwrap();
+
+    let pack = tw_pack_lib::parse_pack_from_bytes(output).unwrap();
+    assert_eq!(pack.get_timestamp(), 42);
+    assert_eq!(pack.get_pack_file_index(), vec!["test1.pack".to_owned(), "test2.pack".to_owned()]);
+    let paths = pack.entries().map(|packed_file| packed_file.unwrap().path).collect::<Vec<String>>();
+    assert_eq!(paths, vec!["ui\\frontend ui\\hello.txt", "ui\\frontend ui\\world.txt"]);
+}
+
+fn repack_legacy(name: &str, version: PFHVersion, bitmask: PFHFlags, file_type: PFHFileType, pfh_timestamp: u64) {
+    let original = format!("tests/test_{}.pack.bk", name);
+    let copy = format!("tests/build/repack_{}.pack", name);
+    let pack = tw_pack_lib::parse_pack(File::open(&original).unwrap()).unwrap();
+    let pack_files = pack.get_pack_file_index();
+    let mut builder = tw_pack_lib::PackBuilder::new(version, file_type);
+    builder.set_bitmask(bitmask).set_timestamp(pfh_timestamp).set_compression(PFHCompressionPolicy::PerPackedFile);
+    for pack_file in &pack_files {
+        builder.add_pack_file(pack_file);
+    }
+    for packed_file in pack.into_iter() {
+        builder.add_packed_file(packed_file);
+    }
+    builder.finish(&mut File::create(&copy).unwrap()).unwrap();
+    assert_eq!(fs::read(&original).unwrap(), fs::read(&copy).unwrap());
+}
+
+#[test]
+fn test_build_legacy_packs() {
+    repack_legacy("pfh3", PFHVersion::PFH3, PFHFlags::HAS_INDEX_WITH_TIMESTAMPS, PFHFileType::Mod, 0x01D3_5A2B_0000_0042);
+    repack_legacy("pfh2", PFHVersion::PFH2, PFHFlags::HAS_INDEX_WITH_TIMESTAMPS, PFHFileType::Patch, 0x01CB_8A2B_0000_0042);
+    repack_legacy("pfh0", PFHVersion::PFH0, PFHFlags::empty(), PFHFileType::Patch, 0);
+
+    // PackedFiles without a timestamp get a zero one in the 64 bits index fields.
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH2, PFHFileType::Mod);
+    builder.set_bitmask(PFHFlags::HAS_INDEX_WITH_TIMESTAMPS)
+        .set_timestamp(0x01D3_5A2B_0000_0042)
+        .add_pack_file("base.pack")
+        .add_packed_file(tw_pack_lib::PackedFile::new(Some(0x01D3_5A2B_0000_0043), "db\\a".to_owned(), b"a".to_vec()))
+        .add_packed_file(tw_pack_lib::PackedFile::new(None, "db\\b".to_owned(), b"bb".to_vec()));
+    let mut output = vec!();
+    builder.finish(&mut output).unwrap();
+
+    let pack = tw_pack_lib::parse_pack_from_bytes(output).unwrap();
+    assert_eq!(pack.get_version(), PFHVersion::PFH2);
+    assert_eq!(pack.get_header().get_header_size(), 0x20);
+    assert_eq!(pack.get_timestamp(), 0x01D3_5A2B_0000_0042);
+    assert_eq!(pack.get_pack_file_index(), vec!["base.pack".to_owned()]);
+    let packed_files: Vec<tw_pack_lib::PackedFile> = pack.into_iter().collect();
+    assert_eq!(packed_files[0].timestamp, Some(0x01D3_5A2B_0000_0043));
+    assert_eq!(packed_files[1].timestamp, Some(0));
+    assert_eq!(&**packed_files[0].get_data().unwrap(), b"a");
+    assert_eq!(&**packed_files[1].get_data().unwrap(), b"bb");
+
+    // Newer PackFiles have 32 bits header timestamps, so a `FILETIME` can't be repacked into them as it is.
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Mod);
+    builder.set_timestamp(0x01D3_5A2B_0000_0042);
+    match builder.validate() {
+        Err(Error::UnsupportedTimestamp { version: PFHVersion::PFH5, timestamp: 0x01D3_5A2B_0000_0042 }) => {},
+        other => panic!("{:?}", other)
+    }
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_build_pack_into_memory() {
+    let original = fs::read("tests/test_pfh3.pack.bk").unwrap();
+    let pack = tw_pack_lib::parse_pack_from_bytes(original.clone()).unwrap();
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH3, PFHFileType::Mod);
+    builder.set_bitmask(PFHFlags::HAS_INDEX_WITH_TIMESTAMPS).set_timestamp(0x01D3_5A2B_0000_0042);
+    for packed_file in pack.into_iter() {
+        builder.add_packed_file(packed_file);
+    }
+    let mut output: Vec<u8> = vec!();
+    builder.finish(&mut output).unwrap();
+    assert_eq!(original, output);
+
+    // The old build functions don't sort the caller's PackedFiles anymore.
+    let packed_files = vec![
+        tw_pack_lib::PackedFile::new(None, "b".to_owned(), vec![]),
+        tw_pack_lib::PackedFile::new(None, "a".to_owned(), vec![])
+    ];
+    tw_pack_lib::build_pack_from_memory(&packed_files, &mut vec!(), PFHVersion::PFH5, PFHFlags::empty(), PFHFileType::Mod, 0, &[]).unwrap();
+    assert_eq!(packed_files[0].path, "b");
+}
+
+#[test]
+fn test_pack_builder() {
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Mod);
+    builder.set_bitmask(PFHFlags::HAS_INDEX_WITH_TIMESTAMPS)
+        .set_timestamp(42)
+        .set_compression(PFHCompressionPolicy::Extensions(vec!["lua".to_owned()]))
+        .add_pack_file("data.pack")
+        .add_bytes("script\\b.lua", b"print('b')".repeat(20))
+        .add_bytes("db\\a", b"a".to_vec());
+    builder.add_reader("text\\c.txt", Cursor::new(b"c".to_vec())).unwrap();
+    builder.add_file("text\\d.txt", Path::new("tests/test_pfh0.pack.bk")).unwrap();
+    builder.validate().unwrap();
+    let mut output = vec!();
+    builder.finish(&mut output).unwrap();
+
+    let pack = tw_pack_lib::parse_pack_from_bytes(output).unwrap();
+    assert_eq!(pack.get_timestamp(), 42);
+    assert_eq!(pack.get_pack_file_index(), vec!["data.pack".to_owned()]);
+    let packed_files: Vec<tw_pack_lib::PackedFile> = pack.into_iter().collect();
+    let paths: Vec<&str> = packed_files.iter().map(|packed_file| packed_file.path.as_ref()).collect();
+    assert_eq!(paths, vec!["db\\a", "script\\b.lua", "text\\c.txt", "text\\d.txt"]);
+    assert!(packed_files[1].is_compressed);
+    assert_eq!(*packed_files[1].get_data().unwrap(), b"print('b')".repeat(20));
+    assert_eq!(&**packed_files[2].get_data().unwrap(), b"c");
+    assert_eq!(*packed_files[3].get_data().unwrap(), fs::read("tests/test_pfh0.pack.bk").unwrap());
+}
+
+#[test]
+fn test_pack_builder_validation() {
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Mod);
+    builder.add_bytes("db\\a", vec![]).add_bytes("DB/A", vec![]);
+    match builder.validate() {
+        Err(Error::InvalidPathError { ref path, .. }) if path == "DB/A" => {},
+        other => panic!("{:?}", other)
+    }
+    let mut output = vec!();
+    assert!(builder.finish(&mut output).is_err());
+    assert!(output.is_empty());
+
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH4, PFHFileType::Mod);
+    builder.set_compression(PFHCompressionPolicy::All);
+    match builder.validate() {
+        Err(Error::UnsupportedCompression { version: PFHVersion::PFH4 }) => {},
+        other => panic!("{:?}", other)
+    }
+
+    // PFH2 and PFH3 timestamps don't fit in the 32 bits fields of newer PackFiles.
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Mod);
+    builder.set_bitmask(PFHFlags::HAS_INDEX_WITH_TIMESTAMPS)
+        .add_packed_file(tw_pack_lib::PackedFile::new(Some(0x01D3_5A2B_0000_0042), "a.txt".to_owned(), vec![]));
+    match builder.validate() {
+        Err(Error::PackedFileError { ref path, ref source }) if path == "a.txt" => match **source {
+            Error::UnsupportedTimestamp { version: PFHVersion::PFH5, timestamp: 0x01D3_5A2B_0000_0042 } => {},
+            ref other => panic!("{:?}", other)
+        },
+        other => panic!("{:?}", other)
+    }
+    builder.set_bitmask(PFHFlags::empty());
+    builder.validate().unwrap();
+
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH4, PFHFileType::Mod);
+    builder.set_bitmask(PFHFlags::HAS_BIG_HEADER | PFHFlags::HAS_INDEX_WITH_TIMESTAMPS);
+    match builder.validate() {
+        Err(Error::UnsupportedBitmask { version: PFHVersion::PFH4, flags }) => assert_eq!(flags, PFHFlags::HAS_BIG_HEADER),
+        other => panic!("{:?}", other)
+    }
+}
+
+#[test]
+fn test_save_pack_atomically() {
+    let path = Path::new("tests/build/atomic_test.pack");
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Mod);
+    builder.add_bytes("a.txt", b"a".to_vec());
+    builder.save(path, false).unwrap();
+    let original = fs::read(path).unwrap();
+
+    // A PackedFile failing to load halfway through the save leaves the previous file untouched.
+    let mut compressed_builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Mod);
+    compressed_builder.set_compression(PFHCompressionPolicy::All).add_bytes("b.txt", b"b".repeat(100));
+    let mut corrupted = vec!();
+    compressed_builder.finish(&mut corrupted).unwrap();
+    let content_position = corrupted.len() - 10;
+    corrupted[content_position] ^= 0xff;
+    let pack = tw_pack_lib::parse_pack_from_bytes(corrupted).unwrap();
+
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Mod);
+    builder.add_bytes("a.txt", b"a".to_vec()).add_packed_file(pack.get("b.txt").unwrap());
+    match builder.save(path, true) {
+        Err(Error::PackedFileError { ref path, .. }) if path == "b.txt" => {},
+        other => panic!("{:?}", other)
+    }
+    assert_eq!(fs::read(path).unwrap(), original);
+    assert!(!Path::new("tests/build/atomic_test.pack.bak").exists());
+    let leftovers = fs::read_dir("tests/build").unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.starts_with(".atomic_test.pack"))
+        .count();
+    assert_eq!(leftovers, 0);
+}
+
+#[test]
+fn test_save_pack_from_threads() {
+    // Every save gets his own temporary file, so concurrent saves to the same path don't write into each other's.
+    let threads = (0..8).map(|i| thread::spawn(move || {
+        let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Mod);
+        builder.add_bytes("a.txt", vec![i; 0x1000]);
+        builder.save(Path::new("tests/build/threads_test.pack"), false)
+    })).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap().unwrap();
+    }
+
+    let pack = tw_pack_lib::parse_pack(File::open("tests/build/threads_test.pack").unwrap()).unwrap();
+    let data = pack.get("a.txt").unwrap().get_data().unwrap();
+    assert!(data.iter().all(|byte| *byte == data[0]));
+    let leftovers = fs::read_dir("tests/build").unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.starts_with(".threads_test.pack"))
+        .count();
+    assert_eq!(leftovers, 0);
+}
+
+#[test]
+fn test_build_legacy_pack_with_invalid_bitmask() {
+    let mut f = File::create(Path::new("tests/build/invalid_bitmask.pack")).unwrap();
+    for &(version, bitmask) in &[(PFHVersion::PFH0, PFHFlags::HAS_INDEX_WITH_TIMESTAMPS), (PFHVersion::PFH3, PFHFlags::HAS_ENCRYPTED_INDEX)] {
+        let mut builder = tw_pack_lib::PackBuilder::new(version, PFHFileType::Mod);
+        builder.set_bitmask(bitmask).add_bytes("a.txt", vec![]);
+        match builder.finish(&mut f) {
+            Err(Error::UnsupportedBitmask { .. }) => {},
+            other => panic!("{:?}", other)
+        }
+    }
+}
+
+#[test]
+fn test_build_pfh6_pack() {
+    let subheader = PFHSubheader {
+        game_version: 7,
+        build_number: 1234,
+        authoring_tool: "CA_TOOL".to_owned(),
+        ..PFHSubheader::default()
+    };
+    let packed_files = vec![
+        tw_pack_lib::PackedFile::new(Some(1), "script\\b.lua".to_owned(), b"b".to_vec()),
+        tw_pack_lib::PackedFile::new(Some(2), "script\\a.lua".to_owned(), b"aa".to_vec())
+    ];
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH6, PFHFileType::Mod);
+    builder.set_bitmask(PFHFlags::HAS_INDEX_WITH_TIMESTAMPS).set_timestamp(42).set_subheader(subheader.clone());
+    for packed_file in &packed_files {
+        builder.add_packed_file(packed_file.clone());
+    }
+    builder.finish(&mut File::create(Path::new("tests/build/pfh6_test.pack")).unwrap()).unwrap();
+
+    let pack = tw_pack_lib::parse_pack(File::open(Path::new("tests/build/pfh6_test.pack")).unwrap()).unwrap();
+    assert_eq!(pack.get_version(), PFHVersion::PFH6);
+    assert_eq!(pack.get_timestamp(), 42);
+    assert_eq!(pack.get_game_version(), Some(7));
+    assert_eq!(pack.get_build_number(), Some(1234));
+    assert_eq!(pack.get_subheader(), Some(subheader));
+
+    let packed_files: Vec<tw_pack_lib::PackedFile> = pack.into_iter().collect();
+    assert_eq!(packed_files[0].path, "script\\a.lua");
+    assert_eq!(packed_files[0].timestamp, Some(2));
+    assert_eq!(&**packed_files[0].get_data().unwrap(), b"aa");
+    assert_eq!(&**packed_files[1].get_data().unwrap(), b"b");
+}
+
+#[test]
+fn test_pfh6_subheader() {
+    // Non-ASCII names survive a round trip.
+    let subheader = PFHSubheader { authoring_tool: "ÜBER_T".to_owned(), ..PFHSubheader::default() };
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH6, PFHFileType::Mod);
+    builder.set_subheader(subheader.clone());
+    let mut output = vec!();
+    builder.finish(&mut output).unwrap();
+    assert_eq!(tw_pack_lib::parse_pack_from_bytes(output.clone()).unwrap().get_subheader(), Some(subheader));
+
+    // The Subheader has to start with his mark.
+    output[0x1C] = 0;
+    match tw_pack_lib::parse_pack_from_bytes(output) {
+        Err(Error::InvalidHeaderError(_)) => {},
+        other => panic!("{:?}", other.map(|_| ()))
+    }
+
+    for authoring_tool in &["TOO_LONG_", "CA\0TOOL"] {
+        let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH6, PFHFileType::Mod);
+        builder.set_subheader(PFHSubheader { authoring_tool: authoring_tool.to_string(), ..PFHSubheader::default() });
+        match builder.validate() {
+            Err(Error::InvalidSubheaderError(_)) => {},
+            other => panic!("{:?}", other)
+        }
+    }
+}
+
+#[test]
+fn test_build_pfh5_pack_with_compressed_flag() {
+    let mut compressed = tw_pack_lib::PackedFile::new(None, "db\\a".to_owned(), b"compressed".to_vec());
+    compressed.is_compressed = true;
+    let packed_files = vec![compressed, tw_pack_lib::PackedFile::new(None, "db\\b".to_owned(), b"plain".to_vec())];
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Mod);
+    builder.set_timestamp(42).set_compression(PFHCompressionPolicy::PerPackedFile);
+    for packed_file in &packed_files {
+        builder.add_packed_file(packed_file.clone());
+    }
+    builder.finish(&mut File::create(Path::new("tests/build/compressed_flag_test.pack")).unwrap()).unwrap();
+
+    let pack = tw_pack_lib::parse_pack(File::open(Path::new("tests/build/compressed_flag_test.pack")).unwrap()).unwrap();
+    let packed_files: Vec<tw_pack_lib::PackedFile> = pack.into_iter().collect();
+    assert!(packed_files[0].is_compressed);
+    assert!(!packed_files[1].is_compressed);
+    assert_eq!(&**packed_files[0].get_data().unwrap(), b"compressed");
+}
+
+#[test]
+fn test_build_pack_with_compression_policy() {
+    let packed_files = vec![
+        tw_pack_lib::PackedFile::new(None, "script\\a.LUA".to_owned(), b"print('a')".repeat(50)),
+        tw_pack_lib::PackedFile::new(None, "db\\b".to_owned(), b"b".to_vec())
+    ];
+    let policy = PFHCompressionPolicy::Extensions(vec!["lua".to_owned()]);
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH6, PFHFileType::Mod);
+    builder.set_timestamp(42).set_compression(policy.clone());
+    for packed_file in &packed_files {
+        builder.add_packed_file(packed_file.clone());
+    }
+    builder.finish(&mut File::create(Path::new("tests/build/compression_policy_test.pack")).unwrap()).unwrap();
+
+    let pack = tw_pack_lib::parse_pack(File::open(Path::new("tests/build/compression_policy_test.pack")).unwrap()).unwrap();
+    let packed_files: Vec<tw_pack_lib::PackedFile> = pack.into_iter().collect();
+    assert!(!packed_files[0].is_compressed);
+    assert!(packed_files[1].is_compressed);
+    assert_eq!(*packed_files[1].get_data().unwrap(), b"print('a')".repeat(50));
+
+    let mut f = File::create(Path::new("tests/build/compression_policy_pfh4_test.pack")).unwrap();
+    let pfh4_builder = |compression: PFHCompressionPolicy| {
+        let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH4, PFHFileType::Mod);
+        builder.set_compression(compression);
+        for packed_file in &packed_files {
+            builder.add_packed_file(packed_file.clone());
+        }
+        builder
+    };
+    for policy in [policy, PFHCompressionPolicy::PerPackedFile].iter() {
+        match pfh4_builder(policy.clone()).finish(&mut f) {
+            Err(Error::UnsupportedCompression { .. }) => {},
+            other => panic!("{:?}", other)
+        }
+    }
+    pfh4_builder(PFHCompressionPolicy::None).finish(&mut f).unwrap();
+}
+
+#[test]
+fn test_repack_raw_packed_files() {
+    let big_data = (0..0x30_0000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+    let bitmask = PFHFlags::HAS_ENCRYPTED_CONTENT | PFHFlags::HAS_ENCRYPTED_INDEX;
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Movie);
+    builder.set_bitmask(bitmask)
+        .add_bytes("music\\a.wem", b"odd".to_vec())
+        .add_bytes("music\\b.wem", big_data.clone());
+    let mut original = vec!();
+    builder.finish(&mut original).unwrap();
+
+    // The padding after the first PackedFile is not part of his data, so it's only kept if the data is copied without re-encrypting it.
+    let pack = tw_pack_lib::parse_pack_from_bytes(original.clone()).unwrap();
+    let padding_position = ((pack.get_header().get_content_position() + 7) & !7) as usize + 5;
+    original[padding_position] ^= 0xff;
+
+    let repack = |version: PFHVersion, bitmask: PFHFlags| {
+        let pack = tw_pack_lib::parse_pack_from_bytes(original.clone()).unwrap();
+        let mut builder = tw_pack_lib::PackBuilder::new(version, PFHFileType::Movie);
+        builder.set_bitmask(bitmask);
+        for packed_file in pack.into_iter() {
+            builder.add_packed_file(packed_file);
+        }
+        let mut repacked = vec!();
+        builder.finish(&mut repacked).unwrap();
+        repacked
+    };
+    assert_eq!(repack(PFHVersion::PFH5, bitmask), original);
+
+    // Different formats still need the data to be decrypted.
+    for &(version, bitmask) in &[(PFHVersion::PFH4, bitmask), (PFHVersion::PFH5, PFHFlags::empty())] {
+        let pack = tw_pack_lib::parse_pack_from_bytes(repack(version, bitmask)).unwrap();
+        assert_eq!(*pack.get("music\\a.wem").unwrap().get_data().unwrap(), b"odd".to_vec());
+        assert_eq!(*pack.get("music\\b.wem").unwrap().get_data().unwrap(), big_data);
+    }
+}
+
+#[test]
+fn test_build_pack_with_encrypted_content() {
+    let data: Vec<Vec<u8>> = vec![b"odd".to_vec(), b"exactly8".to_vec(), vec![], (0..100).collect()];
+    for &(version, bitmask) in &[(PFHVersion::PFH5, PFHFlags::HAS_ENCRYPTED_CONTENT | PFHFlags::HAS_ENCRYPTED_INDEX),
+                                 (PFHVersion::PFH5, PFHFlags::HAS_ENCRYPTED_CONTENT | PFHFlags::HAS_BIG_HEADER),
+                                 (PFHVersion::PFH4, PFHFlags::HAS_ENCRYPTED_CONTENT | PFHFlags::HAS_INDEX_WITH_TIMESTAMPS)] {
+        let packed_files: Vec<tw_pack_lib::PackedFile> = data.iter().enumerate()
+            .map(|(i, data)| tw_pack_lib::PackedFile::new(Some(i as u64), format!("music\\{}.wem", i), data.clone()))
+            .collect();
+        let mut builder = tw_pack_lib::PackBuilder::new(version, PFHFileType::Movie);
+        builder.set_bitmask(bitmask).set_timestamp(42).add_pack_file("music.pack");
+        for packed_file in &packed_files {
+            builder.add_packed_file(packed_file.clone());
+        }
+        builder.finish(&mut File::create(Path::new("tests/build/encrypted_content_test.pack")).unwrap()).unwrap();
+
+        let pack = tw_pack_lib::parse_pack(File::open(Path::new("tests/build/encrypted_content_test.pack")).unwrap()).unwrap();
+        assert_eq!(pack.get_bitmask(), bitmask);
+        let packed_files: Vec<tw_pack_lib::PackedFile> = pack.into_iter().collect();
+        assert_eq!(packed_files.len(), data.len());
+        for (i, packed_file) in packed_files.iter().enumerate() {
+            assert_eq!(packed_file.path, format!("music\\{}.wem", i));
+            assert_eq!(*packed_file.get_data().unwrap(), data[i]);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs::File;
+use std::path::Path;
+
+use tw_pack_lib;
+use tw_pack_lib::EditablePackFile;
+use tw_pack_lib::PFHVersion;
+use tw_pack_lib::PFHFlags;
+use tw_pack_lib::PFHFileType;
+use tw_pack_lib::PFHCompressionPolicy;
+use tw_pack_lib::error::Error;
+
+fn build_test_pack(path: &Path) {
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Mod);
+    builder.set_bitmask(PFHFlags::HAS_INDEX_WITH_TIMESTAMPS)
+        .set_timestamp(1234)
+        .set_compression(PFHCompressionPolicy::Extensions(vec!["loc".to_owned()]))
+        .add_pack_file("base.pack")
+        .add_bytes("db\\units_tables\\units", b"units".to_vec())
+        .add_bytes("text\\db\\units.loc", b"unit names".to_vec())
+        .add_bytes("script\\old.lua", b"old".to_vec());
+    builder.finish(&mut File::create(path).unwrap()).unwrap();
+}
+
+#[test]
+fn test_edit_pack() {
+    let path = Path::new("tests/edit/edit_test.pack");
+    build_test_pack(path);
+
+    let mut pack = EditablePackFile::open(path).unwrap();
+    assert!(pack.contains("DB/units_tables/units"));
+    pack.insert(tw_pack_lib::PackedFile::new(Some(5), "script\\new.lua".to_owned(), b"new".to_vec()));
+    assert!(pack.remove("script/old.lua").is_some());
+    assert!(pack.remove("script/old.lua").is_none());
+    pack.rename("db\\units_tables\\units", "db\\units_tables\\data__").unwrap();
+    pack.replace("text/db/units.loc", b"new unit names".to_vec()).unwrap();
+
+    match pack.rename("script\\missing.lua", "script\\other.lua") {
+        Err(Error::InvalidPathError { ref path, .. }) if path == "script\\missing.lua" => {},
+        other => panic!("{:?}", other)
+    }
+    match pack.rename("script\\new.lua", "TEXT\\db\\units.loc") {
+        Err(Error::InvalidPathError { ref path, .. }) if path == "TEXT\\db\\units.loc" => {},
+        other => panic!("{:?}", other)
+    }
+    assert!(pack.replace("script\\old.lua", vec![]).is_err());
+
+    // The PackedFiles we didn't touch are still read from the PackFile we're replacing.
+    let original = ::std::fs::read(path).unwrap();
+    pack.set_backup(true).save().unwrap();
+    assert_eq!(::std::fs::read("tests/edit/edit_test.pack.bak").unwrap(), original);
+
+    let saved = tw_pack_lib::parse_pack(File::open(path).unwrap()).unwrap();
+    assert_eq!(saved.get_version(), PFHVersion::PFH5);
+    assert_eq!(saved.get_timestamp(), 1234);
+    assert_eq!(saved.get_pack_file_index(), vec!["base.pack".to_owned()]);
+    let paths = saved.entries().map(|packed_file| packed_file.unwrap().path).collect::<Vec<String>>();
+    assert_eq!(paths, vec!["db\\units_tables\\data__", "script\\new.lua", "text\\db\\units.loc"]);
+    assert_eq!(*saved.get("db\\units_tables\\data__").unwrap().get_data().unwrap(), b"units".to_vec());
+    assert_eq!(saved.get("script\\new.lua").unwrap().timestamp, Some(5));
+    let loc = saved.get("text\\db\\units.loc").unwrap();
+    assert!(loc.is_compressed);
+    assert_eq!(*loc.get_data().unwrap(), b"new unit names".to_vec());
+}
+
+#[test]
+fn test_save_edited_pack_as() {
+    let path = Path::new("tests/edit/edit_as_test.pack");
+    build_test_pack(path);
+
+    let mut pack = EditablePackFile::new(&tw_pack_lib::parse_pack(File::open(path).unwrap()).unwrap()).unwrap();
+    assert_eq!(pack.get_path(), None);
+    match pack.save() {
+        Err(Error::MissingPathError) => {},
+        other => panic!("{:?}", other)
+    }
+
+    let new_path = Path::new("tests/edit/edit_as_test_2.pack");
+    pack.remove("script\\old.lua").unwrap();
+    pack.save_as(new_path).unwrap();
+    assert_eq!(pack.get_path(), Some(new_path));
+
+    let saved = tw_pack_lib::parse_pack(File::open(new_path).unwrap()).unwrap();
+    assert!(!saved.contains("script\\old.lua"));
+    assert_eq!(*saved.get("text\\db\\units.loc").unwrap().get_data().unwrap(), b"unit names".to_vec());
+    assert!(tw_pack_lib::parse_pack(File::open(path).unwrap()).unwrap().contains("script\\old.lua"));
+
+    let mut written = vec!();
+    pack.write(&mut written).unwrap();
+    assert_eq!(written, ::std::fs::read(new_path).unwrap());
+}
@@ -1,6 +1,7 @@
 extern crate tw_pack_lib;
 
 mod build;
+mod edit;
 mod parse;
 
 use std::fs;
@@ -11,44 +12,51 @@ use tw_pack_lib::PackedFile;
 use tw_pack_lib::PFHVersion;
 use tw_pack_lib::PFHFileType;
 use tw_pack_lib::PFHFlags;
+use tw_pack_lib::PFHCompressionPolicy;
 
 #[test]
 fn test_repack() {
     fs::copy("tests/twa_boot.pack.bk", "tests/repack_twa_boot.pack").unwrap();
     let f = File::open(Path::new("tests/repack_twa_boot.pack")).expect("file not found");
     let pack = tw_pack_lib::parse_pack(f).unwrap();
-    let mut packed_files: Vec<PackedFile> = pack.into_iter().collect();
+    let packed_files: Vec<PackedFile> = pack.into_iter().collect();
 
     for packed_file in &packed_files {
         println!("{:?}", packed_file.get_data().unwrap())
     }
 
-    let mut f = File::create(Path::new("tests/repack_twa_boot.pack")).expect("cannot open file");
-    tw_pack_lib::build_pack_from_memory(&mut packed_files,
-        &mut f,
-        PFHVersion::PFH5,
-        PFHFlags::HAS_BIG_HEADER | PFHFlags::HAS_INDEX_WITH_TIMESTAMPS,
-        PFHFileType::Boot,
-        42,
-        &[]).unwrap()
+    // We replace the PackFile we're reading from, so it has to be done atomically.
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Boot);
+    builder.set_bitmask(PFHFlags::HAS_BIG_HEADER | PFHFlags::HAS_INDEX_WITH_TIMESTAMPS)
+        .set_timestamp(42)
+        .set_compression(PFHCompressionPolicy::PerPackedFile);
+    for packed_file in &packed_files {
+        builder.add_packed_file(packed_file.clone());
+    }
+    builder.save(Path::new("tests/repack_twa_boot.pack"), true).unwrap();
 
+    assert_eq!(fs::read("tests/repack_twa_boot.pack.bak").unwrap(), fs::read("tests/twa_boot.pack.bk").unwrap());
+    let pack = tw_pack_lib::parse_pack(File::open(Path::new("tests/repack_twa_boot.pack")).unwrap()).unwrap();
+    assert_eq!(pack.get_timestamp(), 42);
+    assert_eq!(pack.into_iter().count(), packed_files.len());
 }
 
 #[test]
+#[allow(deprecated)]
 fn test_pack_file_index() {
     fs::copy("tests/test_pack_file_index.pack.bk", "tests/test_pack_file_index.pack").unwrap();
     let f = File::open(Path::new("tests/test_pack_file_index.pack")).expect("file not found");
     let pack = tw_pack_lib::parse_pack(f).unwrap();
     let pack_files = pack.get_pack_file_index();
     println!("{:?}", pack_files);
-    let mut packed_files: Vec<PackedFile> = pack.into_iter().collect();
+    let packed_files: Vec<PackedFile> = pack.into_iter().collect();
 
     for packed_file in &packed_files {
         println!("{:?}", packed_file.get_data().unwrap())
     }
 
     let mut f = File::create(Path::new("tests/test_pack_file_index_copy.pack")).expect("cannot open file");
-    tw_pack_lib::build_pack_from_memory(&mut packed_files,
+    tw_pack_lib::build_pack_from_memory(&packed_files,
         &mut f,
         PFHVersion::PFH5,
         PFHFlags::empty(),
@@ -61,3 +69,82 @@ fn test_pack_file_index() {
     let pack_files_copy = pack.get_pack_file_index();
     assert_eq!(pack_files, pack_files_copy);
 }
+
+#[test]
+fn test_pack_builder_pack_file_index() {
+    let pack = tw_pack_lib::parse_pack(File::open(Path::new("tests/test_pack_file_index.pack.bk")).unwrap()).unwrap();
+    let pack_files = pack.get_pack_file_index();
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Boot);
+    builder.set_timestamp(42);
+    for pack_file in &pack_files {
+        builder.add_pack_file(pack_file);
+    }
+    for packed_file in pack.into_iter() {
+        builder.add_packed_file(packed_file);
+    }
+    let mut output = vec!();
+    builder.finish(&mut output).unwrap();
+    assert_eq!(tw_pack_lib::parse_pack_from_bytes(output).unwrap().get_pack_file_index(), pack_files);
+}
+
+#[test]
+fn test_repack_encrypted_index() {
+    let f = File::open(Path::new("tests/twa_boot.pack.bk")).expect("file not found");
+    let pack = tw_pack_lib::parse_pack(f).unwrap();
+    let bitmask = pack.get_bitmask();
+    assert!(bitmask.contains(PFHFlags::HAS_ENCRYPTED_INDEX));
+    let packed_files: Vec<PackedFile> = pack.into_iter().collect();
+
+    let mut f = File::create(Path::new("tests/repack_encrypted_index_twa_boot.pack")).expect("cannot open file");
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Boot);
+    builder.set_bitmask(bitmask).set_compression(PFHCompressionPolicy::PerPackedFile);
+    for packed_file in &packed_files {
+        builder.add_packed_file(packed_file.clone());
+    }
+    builder.finish(&mut f).unwrap();
+
+    // Everything after the header, except the signature, must be identical.
+    let original = fs::read("tests/twa_boot.pack.bk").unwrap();
+    let repacked = fs::read("tests/repack_encrypted_index_twa_boot.pack").unwrap();
+    assert_eq!(&original[0x30..0x42b7], &repacked[0x30..]);
+
+    let pack = tw_pack_lib::parse_pack(File::open(Path::new("tests/repack_encrypted_index_twa_boot.pack")).unwrap()).unwrap();
+    let repacked_files: Vec<PackedFile> = pack.into_iter().collect();
+    assert_eq!(packed_files.len(), repacked_files.len());
+    for (packed_file, repacked_file) in packed_files.iter().zip(&repacked_files) {
+        assert_eq!(packed_file.path, repacked_file.path);
+        assert_eq!(packed_file.get_data().unwrap(), repacked_file.get_data().unwrap());
+    }
+}
+
+#[test]
+fn test_repack_arena_pack() {
+    let f = File::open(Path::new("tests/twa_boot.pack.bk")).expect("file not found");
+    let pack = tw_pack_lib::parse_pack(f).unwrap();
+    let bitmask = pack.get_bitmask();
+    assert!(bitmask.contains(PFHFlags::HAS_ENCRYPTED_INDEX));
+    let big_header = pack.get_big_header().unwrap();
+    assert_eq!(big_header.signature_offset, 0x42b7);
+    assert_eq!(big_header.signature.len(), 0x100);
+    let packed_files: Vec<PackedFile> = pack.into_iter().collect();
+
+    let mut f = File::create(Path::new("tests/repack_arena_twa_boot.pack")).expect("cannot open file");
+    let mut builder = tw_pack_lib::PackBuilder::new(PFHVersion::PFH5, PFHFileType::Boot);
+    builder.set_bitmask(bitmask).set_compression(PFHCompressionPolicy::PerPackedFile).set_big_header(big_header.clone());
+    for packed_file in &packed_files {
+        builder.add_packed_file(packed_file.clone());
+    }
+    builder.finish(&mut f).unwrap();
+
+    let original = fs::read("tests/twa_boot.pack.bk").unwrap();
+    let repacked = fs::read("tests/repack_arena_twa_boot.pack").unwrap();
+    assert_eq!(original, repacked);
+
+    let pack = tw_pack_lib::parse_pack(File::open(Path::new("tests/repack_arena_twa_boot.pack")).unwrap()).unwrap();
+    let repacked_files: Vec<PackedFile> = pack.into_iter().collect();
+    assert_eq!(packed_files.len(), repacked_files.len());
+    for (packed_file, repacked_file) in packed_files.iter().zip(&repacked_files) {
+        assert_eq!(packed_file.path, repacked_file.path);
+        assert_eq!(packed_file.get_data().unwrap(), repacked_file.get_data().unwrap());
+    }
+}
@@ -1,5 +1,6 @@
 use fs;
 use std::fs::File;
+use std::io::Cursor;
 use std::path::Path;
 
 use tw_pack_lib;
@@ -10,8 +11,285 @@ fn test_read_header() {
     let f = File::open(Path::new("tests/read_header_twa_boot.pack")).expect("file not found");
     let pack = tw_pack_lib::parse_pack(f).unwrap();
 
+    let header = pack.get_header().clone();
+    assert_eq!(header.version, tw_pack_lib::PFHVersion::PFH5);
+    assert_eq!(header.file_type, tw_pack_lib::PFHFileType::Boot);
+    assert_eq!(header.bitmask, pack.get_bitmask());
+    assert_eq!(header.get_header_size(), 0x30);
+    assert_eq!(header.big_header.unwrap().signature_offset, 0x42b7);
+    assert_eq!(header.subheader, None);
+
     for item in pack.into_iter() {
         println!("{}", item);
         item.get_data().unwrap();
     }
 }
+
+#[test]
+fn test_read_corrupt_signature_offset() {
+    let mut data = fs::read("tests/twa_boot.pack.bk").unwrap();
+    let content_position = tw_pack_lib::parse_pack_from_bytes(data.clone()).unwrap().get_header().get_content_position() as u32;
+
+    // An offset before the content means there is no signature.
+    data[0x28..0x2C].copy_from_slice(&[0x10, 0, 0, 0]);
+    let pack = tw_pack_lib::parse_pack_from_bytes(data.clone()).unwrap();
+    assert_eq!(pack.get_signature().unwrap().len(), 0);
+
+    // And we don't read more than a signature from it.
+    data[0x28..0x2C].copy_from_slice(&[content_position as u8, (content_position >> 8) as u8, (content_position >> 16) as u8, (content_position >> 24) as u8]);
+    let pack = tw_pack_lib::parse_pack_from_bytes(data).unwrap();
+    assert_eq!(pack.get_signature().unwrap().len(), 0x100);
+}
+
+#[test]
+fn test_read_big_header_without_signature_offset() {
+    // Older versions of this crate wrote every field of the big header as zero, the signature offset included.
+    let mut data = fs::read("tests/twa_boot.pack.bk").unwrap();
+    for byte in &mut data[0x1C..0x30] {
+        *byte = 0;
+    }
+    let pack = tw_pack_lib::parse_pack_from_bytes(data).unwrap();
+    let big_header = pack.get_big_header().unwrap();
+    assert_eq!(big_header.signature_offset, 0);
+    assert!(big_header.signature.is_empty());
+    for packed_file in pack.entries() {
+        packed_file.unwrap().get_data().unwrap();
+    }
+}
+
+#[test]
+fn test_read_pfh3() {
+    fs::copy("tests/test_pfh3.pack.bk", "tests/read_pfh3.pack").unwrap();
+    let f = File::open(Path::new("tests/read_pfh3.pack")).expect("file not found");
+    let pack = tw_pack_lib::parse_pack(f).unwrap();
+    assert_eq!(pack.get_version(), tw_pack_lib::PFHVersion::PFH3);
+    assert_eq!(pack.get_file_type(), tw_pack_lib::PFHFileType::Mod);
+    assert_eq!(pack.get_timestamp(), 0x01D3_5A2B_0000_0042);
+
+    let packed_files: Vec<tw_pack_lib::PackedFile> = pack.into_iter().collect();
+    assert_eq!(packed_files.len(), 2);
+    assert_eq!(packed_files[0].path, "db\\units_tables\\units");
+    assert_eq!(packed_files[0].timestamp, Some(0x01D3_5A2B_C0FF_EE00));
+    assert_eq!(&**packed_files[0].get_data().unwrap(), b"hello shogun");
+    assert_eq!(packed_files[1].path, "script\\campaign.lua");
+    assert_eq!(&**packed_files[1].get_data().unwrap(), b"print('ok')\n");
+}
+
+#[test]
+fn test_read_pfh2() {
+    let pack = tw_pack_lib::parse_pack_from_bytes(fs::read("tests/test_pfh2.pack.bk").unwrap()).unwrap();
+    assert_eq!(pack.get_version(), tw_pack_lib::PFHVersion::PFH2);
+    assert_eq!(pack.get_file_type(), tw_pack_lib::PFHFileType::Patch);
+    assert_eq!(pack.get_bitmask(), tw_pack_lib::PFHFlags::HAS_INDEX_WITH_TIMESTAMPS);
+    assert_eq!(pack.get_timestamp(), 0x01CB_8A2B_0000_0042);
+    assert_eq!(pack.get_pack_file_index(), vec!["patch.pack".to_owned()]);
+
+    let packed_files: Vec<tw_pack_lib::PackedFile> = pack.into_iter().collect();
+    assert_eq!(packed_files.len(), 2);
+    assert_eq!(packed_files[0].path, "db\\units_tables\\units");
+    assert_eq!(packed_files[0].timestamp, Some(0x01CB_8A2B_C0FF_EE00));
+    assert_eq!(&**packed_files[0].get_data().unwrap(), b"early shogun");
+    assert_eq!(packed_files[1].path, "script\\battle.lua");
+    assert_eq!(packed_files[1].timestamp, Some(0x01CB_8A2B_C0FF_EE01));
+    assert_eq!(&**packed_files[1].get_data().unwrap(), b"print('pfh2')\n");
+}
+
+#[test]
+fn test_read_pack_from_bytes_and_reader() {
+    let data = fs::read("tests/test_pfh3.pack.bk").unwrap();
+    let from_bytes = tw_pack_lib::parse_pack_from_bytes(data.clone()).unwrap();
+    let from_reader = tw_pack_lib::parse_pack_from_reader(Cursor::new(data)).unwrap();
+
+    for pack in &[from_bytes, from_reader] {
+        assert_eq!(pack.get_version(), tw_pack_lib::PFHVersion::PFH3);
+        assert_eq!(pack.get_timestamp(), 0x01D3_5A2B_0000_0042);
+        let packed_files: Vec<tw_pack_lib::PackedFile> = pack.into_iter().collect();
+        assert_eq!(packed_files.len(), 2);
+        assert_eq!(&**packed_files[0].get_data().unwrap(), b"hello shogun");
+        assert_eq!(&**pack.get("script/campaign.lua").unwrap().get_data().unwrap(), b"print('ok')\n");
+    }
+
+    assert!(tw_pack_lib::parse_pack_from_bytes(b"PFH5".to_vec()).is_err());
+}
+
+#[test]
+fn test_find_packed_files() {
+    fs::copy("tests/test_pfh3.pack.bk", "tests/find_pfh3.pack").unwrap();
+    let f = File::open(Path::new("tests/find_pfh3.pack")).expect("file not found");
+    let pack = tw_pack_lib::parse_pack(f).unwrap();
+
+    let pattern = tw_pack_lib::PathPattern::glob("db/*_tables/*").unwrap();
+    let found: Vec<String> = pack.find(&pattern).map(|packed_file| packed_file.path).collect();
+    assert_eq!(found, vec!["db\\units_tables\\units".to_owned()]);
+
+    let pattern = tw_pack_lib::PathPattern::glob("script\\**\\*.lua").unwrap();
+    let found: Vec<String> = pack.find(&pattern).map(|packed_file| packed_file.path).collect();
+    assert_eq!(found, vec!["script\\campaign.lua".to_owned()]);
+    assert_eq!(pack.find(&tw_pack_lib::PathPattern::glob("*.lua").unwrap()).count(), 0);
+
+    let pattern = tw_pack_lib::PathPattern::regex("^(db|script)/").unwrap();
+    assert_eq!(pack.find(&pattern).count(), 2);
+    assert!(tw_pack_lib::PathPattern::regex("(").is_err());
+}
+
+#[test]
+fn test_pack_tree() {
+    fs::copy("tests/test_pfh3.pack.bk", "tests/tree_pfh3.pack").unwrap();
+    let f = File::open(Path::new("tests/tree_pfh3.pack")).expect("file not found");
+    let pack = tw_pack_lib::parse_pack(f).unwrap();
+    let tree = pack.get_tree();
+
+    assert_eq!(tree.file_count, 2);
+    assert_eq!(tree.size, 24);
+    assert!(tree.files.is_empty());
+    assert_eq!(tree.folders.keys().collect::<Vec<&String>>(), vec!["db", "script"]);
+
+    let folder = tree.get_folder("db/units_tables").unwrap();
+    assert_eq!(folder.name, "units_tables");
+    assert_eq!(folder.file_count, 1);
+    assert_eq!(folder.size, 12);
+    assert_eq!(folder.files["units"].path, "db\\units_tables\\units");
+    assert_eq!(tree.get_folder("script\\").unwrap().files["campaign.lua"].size, 12);
+    assert!(tree.get_folder("ui").is_none());
+}
+
+#[test]
+fn test_read_pfh0() {
+    fs::copy("tests/test_pfh0.pack.bk", "tests/read_pfh0.pack").unwrap();
+    let f = File::open(Path::new("tests/read_pfh0.pack")).expect("file not found");
+    let pack = tw_pack_lib::parse_pack(f).unwrap();
+    assert_eq!(pack.get_version(), tw_pack_lib::PFHVersion::PFH0);
+    assert_eq!(pack.get_file_type(), tw_pack_lib::PFHFileType::Patch);
+    assert_eq!(pack.get_timestamp(), 0);
+    assert_eq!(pack.get_pack_file_index(), vec!["patch.pack".to_owned()]);
+
+    let packed_files: Vec<tw_pack_lib::PackedFile> = pack.into_iter().collect();
+    assert_eq!(packed_files.len(), 2);
+    assert_eq!(packed_files[0].path, "data\\empire.txt");
+    assert_eq!(packed_files[0].timestamp, None);
+    assert_eq!(&**packed_files[0].get_data().unwrap(), b"empire");
+    assert_eq!(&**packed_files[1].get_data().unwrap(), b"napoleon");
+}
+
+#[test]
+fn test_read_compressed_packed_files() {
+    let data = b"compressed, compressed, compressed, compressed, compressed".repeat(100);
+    let mut compressed = tw_pack_lib::PackedFile::new(None, "db\\a".to_owned(), data.clone());
+    compressed.is_compressed = true;
+    let mut builder = tw_pack_lib::PackBuilder::new(tw_pack_lib::PFHVersion::PFH5, tw_pack_lib::PFHFileType::Mod);
+    builder.set_compression(tw_pack_lib::PFHCompressionPolicy::PerPackedFile).add_packed_file(compressed);
+    let mut output = vec!();
+    builder.finish(&mut output).unwrap();
+
+    let pack = tw_pack_lib::parse_pack_from_bytes(output.clone()).unwrap();
+    let packed_file = pack.get("db\\a").unwrap();
+    assert!(packed_file.is_compressed);
+    let raw_data = packed_file.get_raw_data().unwrap();
+    assert!(raw_data.len() < data.len());
+    assert_eq!(&raw_data[0..4], &[(data.len() & 0xff) as u8, (data.len() >> 8) as u8, 0, 0]);
+    assert_eq!(*packed_file.get_data().unwrap(), data);
+
+    // A wrong decompressed size is reported along with the path of the PackedFile, and the error liblzma returned as the cause.
+    let content_position = output.len() - raw_data.len();
+    output[content_position + 1] = 0;
+    let pack = tw_pack_lib::parse_pack_from_bytes(output).unwrap();
+    match pack.get("db/a").unwrap().get_data() {
+        Err(ref error @ tw_pack_lib::error::Error::PackedFileError { .. }) => {
+            assert!(error.to_string().starts_with("error in PackedFile \"db\\a\": I/O error: "));
+            let source = ::std::error::Error::source(error).unwrap();
+            assert!(source.source().is_some());
+        },
+        other => panic!("{:?}", other)
+    }
+}
+
+#[test]
+fn test_entries_report_malformed_index() {
+    let mut data = fs::read("tests/test_pfh0.pack.bk").unwrap();
+    // The second PackedFile now goes past the end of the file.
+    data[0x37] = 0xFF;
+    fs::write("tests/entries_malformed_pfh0.pack", &data).unwrap();
+    let f = File::open(Path::new("tests/entries_malformed_pfh0.pack")).expect("file not found");
+    let pack = tw_pack_lib::parse_pack(f).unwrap();
+
+    assert_eq!(pack.into_iter().count(), 1);
+    let entries: Vec<tw_pack_lib::error::Result<tw_pack_lib::PackedFile>> = pack.entries().collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].as_ref().unwrap().path, "data\\empire.txt");
+    match entries[1] {
+        Err(ref error @ tw_pack_lib::error::Error::IndexEntryError { .. }) => {
+            assert_eq!(error.to_string(), "malformed PackedFile Index entry 1 at offset 0x14 of the Index: the data ends past the end of the file");
+        },
+        _ => panic!("the malformed entry was not reported")
+    }
+
+    fs::copy("tests/test_pfh0.pack.bk", "tests/entries_pfh0.pack").unwrap();
+    let f = File::open(Path::new("tests/entries_pfh0.pack")).expect("file not found");
+    let pack = tw_pack_lib::parse_pack(f).unwrap();
+    assert!(pack.entries().all(|entry| entry.is_ok()));
+    assert_eq!(pack.entries().count(), 2);
+}
+
+fn read_malformed_pack(data: &[u8]) {
+    if let Ok(pack) = tw_pack_lib::parse_pack_from_bytes(data.to_vec()) {
+        pack.get_pack_file_index();
+        pack.get_tree();
+        for packed_file in pack.entries().flatten() {
+            let _ = packed_file.get_data();
+        }
+    }
+}
+
+/// Truncated or corrupted PackFiles have to return errors, not panic. The fuzz targets cover this more thoroughly.
+#[test]
+fn test_read_malformed_packs() {
+    for source in &["tests/test_pfh0.pack.bk", "tests/test_pfh3.pack.bk", "tests/twa_boot.pack.bk"] {
+        let data = fs::read(source).unwrap();
+        for length in (0..data.len()).filter(|length| *length < 0x200 || length % 0x100 == 0) {
+            read_malformed_pack(&data[..length]);
+        }
+        for position in 0..data.len().min(0x200) {
+            let mut corrupted = data.clone();
+            corrupted[position] ^= 0xFF;
+            read_malformed_pack(&corrupted);
+        }
+    }
+}
+
+#[test]
+fn test_validate_pack() {
+    let mut builder = tw_pack_lib::PackBuilder::new(tw_pack_lib::PFHVersion::PFH4, tw_pack_lib::PFHFileType::Mod);
+    builder.add_bytes("a.txt", b"a".to_vec());
+    let mut data = vec!();
+    builder.finish(&mut data).unwrap();
+    tw_pack_lib::parse_pack_from_bytes(data.clone()).unwrap().validate().unwrap();
+
+    // PFH4 PackFiles can't have the extended header, but we can still read them.
+    data[5] |= 0x01;
+    let pack = tw_pack_lib::parse_pack_from_bytes(data).unwrap();
+    assert_eq!(pack.get_bitmask(), tw_pack_lib::PFHFlags::HAS_BIG_HEADER);
+    assert!(pack.get("a.txt").is_some());
+    match pack.validate() {
+        Err(tw_pack_lib::error::Error::UnsupportedBitmask { version: tw_pack_lib::PFHVersion::PFH4, .. }) => {},
+        other => panic!("{:?}", other)
+    }
+}
+
+#[test]
+fn test_get_packed_file_by_path() {
+    fs::copy("tests/test_pfh0.pack.bk", "tests/get_by_path_pfh0.pack").unwrap();
+    let f = File::open(Path::new("tests/get_by_path_pfh0.pack")).expect("file not found");
+    let pack = tw_pack_lib::parse_pack(f).unwrap();
+
+    assert!(pack.contains("data\\empire.txt"));
+    assert!(pack.contains("data/empire.txt"));
+    assert!(!pack.contains("DATA/Empire.txt"));
+    assert!(pack.contains_ignore_case("DATA/Empire.txt"));
+    assert!(!pack.contains("data/shogun.txt"));
+
+    let packed_file = pack.get("data/empire.txt").unwrap();
+    assert_eq!(packed_file.path, "data\\empire.txt");
+    assert_eq!(&**packed_file.get_data().unwrap(), b"empire");
+    assert!(pack.get("DATA/Empire.txt").is_none());
+    assert_eq!(&**pack.get_ignore_case("DATA/Empire.txt").unwrap().get_data().unwrap(), b"empire");
+}